@@ -1,26 +1,20 @@
-use crate::data::{Memory, Program, IO};
-
-mod data;
+use intcode::{parse_memory, Input, Memory, Program};
 
 fn main() {
-    let input: Vec<isize> = include_str!("input.txt")
-        .trim()
-        .split(",")
-        .map(|s| s.parse().unwrap())
-        .collect();
+    let input = parse_memory(include_str!("input.txt")).unwrap();
 
-    println!("part_1: {}", part_1(&input, Some(1)));
-    println!("part_2: {}", part_2(&input, Some(5)));
+    println!("part_1: {}", part_1(&input, vec![1]));
+    println!("part_2: {}", part_2(&input, vec![5]));
 }
 
-fn part_1(memory: &Memory, input: IO) -> isize {
-    let mut program = Program::new(memory.clone(), input);
-    program = program.run();
-    program.output.unwrap()
+fn part_1(memory: &Memory, input: Input) -> isize {
+    let mut program = Program::new(memory.clone(), Some(input));
+    program.run().unwrap();
+    *program.output.last().unwrap()
 }
 
-fn part_2(memory: &Memory, input: IO) -> isize {
-    let mut program = Program::new(memory.clone(), input);
-    program = program.run();
-    program.output.unwrap()
+fn part_2(memory: &Memory, input: Input) -> isize {
+    let mut program = Program::new(memory.clone(), Some(input));
+    program.run().unwrap();
+    *program.output.last().unwrap()
 }
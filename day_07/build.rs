@@ -0,0 +1,13 @@
+// Translate the amplifier program into Rust ahead of time; main.rs includes it as `amplifier`
+use intcode::{parse_memory, translate};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/input.txt");
+
+    let memory = parse_memory(&fs::read_to_string("src/input.txt").unwrap()).unwrap();
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("amplifier.rs");
+    fs::write(path, translate(&memory)).unwrap();
+}
@@ -1,15 +1,15 @@
+use intcode::{parse_memory, Memory, Network, NetworkStop, Program, Routing};
 use itertools::Itertools;
+use std::thread;
 
-use crate::data::{Memory, Program};
-
-mod data;
+// input.txt translated into Rust by build.rs. Its run() falls back to the interpreter for any other
+// program, such as the examples in the tests.
+mod amplifier {
+    include!(concat!(env!("OUT_DIR"), "/amplifier.rs"));
+}
 
 fn main() {
-    let memory: Memory = include_str!("input.txt")
-        .trim()
-        .split(",")
-        .map(|s| s.parse().unwrap())
-        .collect();
+    let memory = parse_memory(include_str!("input.txt")).unwrap();
 
     println!("part_1: {}", part_1(memory.clone()));
     println!("part_2: {}", part_2(memory.clone()));
@@ -26,8 +26,8 @@ fn part_1(memory: Memory) -> isize {
             input.append(previous_amp_outputs);
 
             let program = &mut Program::new(memory.clone(), Some(input));
-            program.run();
-            *previous_amp_outputs = program.output.as_ref().unwrap().clone();
+            amplifier::run(program).unwrap();
+            *previous_amp_outputs = program.output.clone();
         }
 
         if previous_amp_outputs[0] > highest_output {
@@ -38,50 +38,37 @@ fn part_1(memory: Memory) -> isize {
     highest_output
 }
 
+// Every ordering of phase settings is tried at once, each with its amps on threads of their own
 fn part_2(memory: Memory) -> isize {
-    let mut highest_output = 0;
-
-    for combo in (5..=9).permutations(5) {
-        let mut done = false;
-        let mut amp_idx = 0;
-        let mut previous_amp_outputs = vec![0];
-        let mut final_amp_output = 0;
-
-        let amps = &mut vec![
-            Program::new(memory.clone(), Some(vec![*combo.get(0).unwrap()])),
-            Program::new(memory.clone(), Some(vec![*combo.get(1).unwrap()])),
-            Program::new(memory.clone(), Some(vec![*combo.get(2).unwrap()])),
-            Program::new(memory.clone(), Some(vec![*combo.get(3).unwrap()])),
-            Program::new(memory.clone(), Some(vec![*combo.get(4).unwrap()])),
-        ];
-
-        while !done {
-            let amp = &mut amps[amp_idx];
-            amp.input.as_mut().unwrap().append(&mut previous_amp_outputs);
-            amp.run();
-
-            if amp_idx == 4 && amp.exit_code == Some(99) {
-                final_amp_output = amp.output.as_ref().unwrap()[0];
-                done = true;
-            } else {
-                previous_amp_outputs = amp.output.clone().unwrap();
-                amp.output = None;
-                amp.exit_code = None;
-                amp_idx = (amp_idx + 1) % 5;
-            }
-        }
-
-        if final_amp_output > highest_output {
-            highest_output = final_amp_output;
-        }
-    }
+    let networks: Vec<_> = (5..=9)
+        .permutations(5)
+        .map(|combo| {
+            let amps = combo
+                .into_iter()
+                .map(|phase_setting| Program::new(memory.clone(), Some(vec![phase_setting])))
+                .collect();
+
+            thread::spawn(move || {
+                let mut network = Network::new(amps, Routing::Ring);
+                network.programs[0].input.push_back(0);
+                assert_eq!(network.run_threaded(), Ok(NetworkStop::Halted));
+                network
+            })
+        })
+        .collect();
 
-    highest_output
+    networks
+        .into_iter()
+        // The last amp's final output went round to the first, which had already halted
+        .map(|network| *network.join().unwrap().programs[0].input.back().unwrap())
+        .max()
+        .unwrap()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use intcode::HaltReason;
 
     #[test]
     fn test_part_1() {
@@ -111,7 +98,8 @@ mod tests {
     fn test_part_2() {
         assert_eq!(
             part_2(vec![
-                3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5
+                3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28,
+                -1, 28, 1005, 28, 6, 99, 0, 0, 5
             ]),
             139629729,
         );
@@ -124,4 +112,24 @@ mod tests {
             18216,
         );
     }
+
+    #[test]
+    fn test_amplifier() {
+        let memory = parse_memory(include_str!("input.txt")).unwrap();
+
+        for phase_setting in 0..=9 {
+            let mut interpreted = Program::new(memory.clone(), Some(vec![phase_setting, 7]));
+            let mut translated = Program::new(memory.clone(), Some(vec![phase_setting, 7]));
+            loop {
+                let halt_reason = interpreted.run();
+                assert_eq!(amplifier::run(&mut translated), halt_reason);
+                assert_eq!(translated, interpreted);
+                if halt_reason != Ok(HaltReason::NeedsInput) {
+                    break;
+                }
+                interpreted.input.push_back(9);
+                translated.input.push_back(9);
+            }
+        }
+    }
 }
@@ -1,15 +1,7 @@
-#![feature(entry_insert)]
-
-use crate::data::{Memory, Program};
-
-mod data;
+use intcode::{parse_memory, Memory, Program};
 
 fn main() {
-    let memory: Memory = include_str!("input.txt")
-        .trim()
-        .split(",")
-        .map(|s| s.parse().unwrap())
-        .collect();
+    let memory = parse_memory(include_str!("input.txt")).unwrap();
 
     println!("part_1: {}", part_1(memory.clone()));
     println!("part_2: {}", part_2(memory.clone()));
@@ -19,12 +11,12 @@ fn main() {
 // keycode. What BOOST keycode does it produce?
 fn part_1(memory: Memory) -> isize {
     let mut program = Program::new(memory, Some(vec![1]));
-    program.run();
+    program.run().unwrap();
     program.output[0]
 }
 
 fn part_2(memory: Memory) -> isize {
     let mut program = Program::new(memory, Some(vec![2]));
-    program.run();
+    program.run().unwrap();
     program.output[0]
 }
@@ -1,16 +1,11 @@
-#![feature(entry_insert)]
-
-use crate::data::{Memory, Program};
+use intcode::{parse_memory, InputSource, Memory, OutputSink, Program};
+use std::cell::RefCell;
 use std::collections::BTreeMap;
-
-mod data;
+use std::mem;
+use std::rc::Rc;
 
 fn main() {
-    let memory: Memory = include_str!("input.txt")
-        .trim()
-        .split(",")
-        .map(|s| s.parse().unwrap())
-        .collect();
+    let memory = parse_memory(include_str!("input.txt")).unwrap();
 
     println!("part_1: {}", part_1(memory.clone()));
     println!("part_2:");
@@ -25,6 +20,9 @@ type Panels = BTreeMap<Point, isize>;
 struct Robot {
     position: Point,
     facing: Point,
+    panels: Panels,
+    // The program outputs a paint color, then a turn, then a paint color...
+    turn_next: bool,
 }
 
 // The Intcode program will serve as the brain of the robot. The program uses input instructions to
@@ -34,29 +32,16 @@ struct Robot {
 // - The direction the robot should turn: 0=left 90 degrees; 1=right 90 degrees
 // After the robot turns, it move forward exactly one panel. The robot starts facing up.
 fn part_1(memory: Memory) -> usize {
-    let mut program = Program::new(memory, None);
-    let mut panels: Panels = BTreeMap::new();
-    let mut robot = Robot {
-        position: Point(0, 0),
-        facing: Point(0, 1),
-    };
-
-    run_robot(&mut program, &mut panels, &mut robot);
+    let panels = run_robot(memory, BTreeMap::new());
 
     panels.len()
 }
 
 // After starting the robot on a single white panel instead, what does it paint?
 fn part_2(memory: Memory) {
-    let mut program = Program::new(memory, None);
     let mut panels: Panels = BTreeMap::new();
-    let mut robot = Robot {
-        position: Point(0, 0),
-        facing: Point(0, 1),
-    };
-
     panels.insert(Point(0, 0), 1);
-    run_robot(&mut program, &mut panels, &mut robot);
+    let panels = run_robot(memory, panels);
 
     let min_x = *panels.iter().map(|(Point(x, _y), _)| x).min().unwrap();
     let max_x = *panels.iter().map(|(Point(x, _y), _)| x).max().unwrap();
@@ -76,22 +61,45 @@ fn part_2(memory: Memory) {
     }
 }
 
-fn run_robot(program: &mut Program, panels: &mut Panels, robot: &mut Robot) {
-    loop {
-        let current_color = panels.get(&robot.position).unwrap_or(&0);
-        program.input.push(*current_color);
-        program.run();
+// The robot is both the program's input (its camera) and its output (its paint brush and wheels),
+// so it's shared between the two
+fn run_robot(memory: Memory, panels: Panels) -> Panels {
+    let robot = Rc::new(RefCell::new(Robot {
+        position: Point(0, 0),
+        facing: Point(0, 1),
+        panels,
+        turn_next: false,
+    }));
+
+    let mut program = Program::with_devices(memory, robot.clone(), robot.clone());
+    program.run().unwrap();
+
+    let panels = mem::take(&mut robot.borrow_mut().panels);
+    panels
+}
+
+impl InputSource for Robot {
+    fn read(&mut self) -> Option<isize> {
+        Some(*self.panels.get(&self.position).unwrap_or(&0))
+    }
 
-        if program.exit_code == Some(99) {
-            break;
-        }
+    fn unread(&mut self, _value: isize) -> bool {
+        false
+    }
+}
+
+impl OutputSink for Robot {
+    fn write(&mut self, value: isize) {
+        self.turn_next = !self.turn_next;
 
         // Save the paint color
-        let paint_color = program.output[0];
-        panels.insert(robot.position.clone(), paint_color);
+        if self.turn_next {
+            self.panels.insert(self.position.clone(), value);
+            return;
+        }
 
         // Rotate the robot
-        robot.facing = match (&robot.facing, program.output[1]) {
+        self.facing = match (&self.facing, value) {
             // Up
             (Point(0, 1), 0) => Point(-1, 0),
             (Point(0, 1), 1) => Point(1, 0),
@@ -111,15 +119,17 @@ fn run_robot(program: &mut Program, panels: &mut Panels, robot: &mut Robot) {
             // RIP
             _ => panic!(
                 "Robot can't be facing {:?} and turn {}",
-                &robot.facing, program.output[1]
+                &self.facing, value
             ),
         };
 
         // Move the robot
-        robot.position.0 += robot.facing.0;
-        robot.position.1 += robot.facing.1;
+        self.position.0 += self.facing.0;
+        self.position.1 += self.facing.1;
+    }
 
-        // Wipe output
-        program.output.clear();
+    // Paint can't be taken back off, nor the robot moved back
+    fn unwrite(&mut self) -> bool {
+        false
     }
 }
@@ -1,17 +1,10 @@
-#![feature(entry_insert)]
-
-use crate::data::{Memory, Program};
+use intcode::{parse_memory, HaltReason, HookAction, Hooks, Memory, Program};
 use itertools::Itertools;
 use std::collections::BTreeMap;
-
-mod data;
+use std::ops::RangeInclusive;
 
 fn main() {
-    let memory: Memory = include_str!("input.txt")
-        .trim()
-        .split(",")
-        .map(|s| s.parse().unwrap())
-        .collect();
+    let memory = parse_memory(include_str!("input.txt")).unwrap();
 
     println!("part_1: {}", part_1(memory.clone()));
     println!("part_2: {}", part_2(memory.clone()));
@@ -27,7 +20,7 @@ fn main() {
 // How many block tiles are on the screen when the game exits
 fn part_1(memory: Memory) -> usize {
     let mut program = Program::new(memory, None);
-    program.run();
+    program.run().unwrap();
 
     let mut block_tile_count = 0;
 
@@ -47,19 +40,11 @@ fn part_1(memory: Memory) -> usize {
 // sequence of output values like -1,0,12345 would show 12345 as the player's current score.
 // Beat the game by breaking all the blocks. What is your score after the last block is broken?
 fn part_2(memory: Memory) -> isize {
-    let mut program = Program::new(memory, None);
-
-    // Insert to quarters
-    program.memory[0] = 2;
-
-    // Instead of just one paddle tile at the bottom, make the entire bottom a paddle
-    for paddle_idx in 1520..=1557 {
-        program.memory[paddle_idx] = 3;
-    }
+    let mut program = new_game(memory);
 
     // Run the game
     loop {
-        program.run();
+        let halt_reason = program.run().unwrap();
 
         // Uncomment to draw the state of the game! For example:
         //
@@ -92,15 +77,66 @@ fn part_2(memory: Memory) -> isize {
         // draw(&program);
 
         // Game is over; return the score
-        if program.exit_code == Some(99) {
+        if halt_reason == HaltReason::Halted {
             return *program.output.last().unwrap();
         }
 
         // Just tell the "paddle" to "stay put"
-        program.input.push(0);
+        program.input.push_back(0);
     }
 }
 
+fn new_game(memory: Memory) -> Program {
+    let paddle_row = paddle_row(&memory);
+    let mut program = insert_quarters(memory);
+
+    // Instead of just one paddle tile at the bottom, make the entire bottom a paddle, by having the
+    // game see one wherever it looks along the bottom row
+    let mut hooks = Hooks::new();
+    hooks.on_read(paddle_row, |_, _| HookAction::Substitute(3));
+    program.hooks = Some(hooks);
+
+    program
+}
+
+fn insert_quarters(memory: Memory) -> Program {
+    let mut program = Program::new(memory, None);
+
+    // Insert two quarters
+    program.memory[0] = 2;
+
+    program
+}
+
+// The addresses of the cells between the walls on the paddle's row. The game keeps the screen in
+// memory row by row, so this draws the first screen, finds where in memory those tiles are laid
+// out, and picks the paddle's row out of it.
+fn paddle_row(memory: &Memory) -> RangeInclusive<usize> {
+    let mut program = insert_quarters(memory.clone());
+    assert_eq!(program.run(), Ok(HaltReason::NeedsInput));
+
+    let tiles: Vec<(usize, usize, isize)> = program
+        .output
+        .iter()
+        .tuples()
+        .filter(|&(&x, _, _)| x != -1)
+        .map(|(&x, &y, &tile)| (x as usize, y as usize, tile))
+        .collect();
+    let width = tiles.iter().map(|&(x, _, _)| x + 1).max().unwrap();
+    let (_, paddle_y, _) = tiles.iter().find(|&&(_, _, tile)| tile == 3).unwrap();
+
+    let screen = (0..program.memory.len())
+        .find(|&screen| {
+            tiles
+                .iter()
+                .all(|&(x, y, tile)| program.memory.get(screen + y * width + x) == Some(tile))
+        })
+        .unwrap();
+    let row = screen + paddle_y * width;
+
+    (row + 1)..=(row + width - 2)
+}
+
 #[derive(Ord, PartialOrd, Eq, PartialEq, Debug)]
 // y, x
 struct Coord(isize, isize);
@@ -157,10 +193,38 @@ fn draw(program: &Program) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use intcode::Recording;
 
     #[test]
     fn test_part_1() {}
 
     #[test]
     fn test_part_2() {}
+
+    #[test]
+    fn test_paddle_row() {
+        let memory = parse_memory(include_str!("input.txt")).unwrap();
+        assert_eq!(paddle_row(&memory), 1520..=1557);
+    }
+
+    #[test]
+    fn test_replay() {
+        let memory = parse_memory(include_str!("input.txt")).unwrap();
+
+        // Play a game moving the paddle about, and save what was played
+        let mut program = new_game(memory.clone());
+        program.recording = Some(Recording::new());
+        let mut moves = [-1, 0, 1].iter().cycle();
+        while program.run().unwrap() == HaltReason::NeedsInput {
+            program.input.push_back(*moves.next().unwrap());
+        }
+        let saved = program.recording.as_ref().unwrap().to_string();
+
+        // Playing it back has to go just the same
+        let recording: Recording = saved.parse().unwrap();
+        let mut replayed = new_game(memory);
+        assert_eq!(replayed.replay(&recording), Ok(HaltReason::Halted));
+        assert_eq!(replayed.output, program.output);
+        assert_eq!(replayed.steps, program.steps);
+    }
 }
@@ -1,19 +1,9 @@
-#![feature(entry_insert)]
+use std::collections::{HashSet, VecDeque};
 
-use std::collections::HashSet;
-use std::fmt;
-use std::fmt::{Debug, Formatter};
-
-use crate::intcode::{Memory, Program};
-
-mod intcode;
+use intcode::{parse_memory, Memory, Program};
 
 fn main() {
-    let memory: Memory = include_str!("input.txt")
-        .trim()
-        .split(",")
-        .map(|s| s.parse().unwrap())
-        .collect();
+    let memory = parse_memory(include_str!("input.txt")).unwrap();
 
     println!("part_1: {}", part_1(memory.clone()));
     println!("part_2: {}", part_2(memory.clone()));
@@ -47,41 +37,36 @@ enum WalkMode {
     Full,
 }
 
-fn walk_maze(mut program: Program, mode: WalkMode) -> (isize, Program) {
+// Breadth-first search, where each node to visit keeps a snapshot of the droid standing on it.
+// Rather than driving the droid back and forth between nodes, each probe forks the VM from that
+// snapshot.
+fn walk_maze(program: Program, mode: WalkMode) -> (isize, Program) {
     let move_commands = [1, 2, 3, 4];
     let move_dirs = ['N', 'S', 'W', 'E'];
-    let reverse_commands = [2, 1, 4, 3];
-    let reverse_of = |cmd: &isize| -> isize {
-        let idx = move_commands.iter().position(|c| c == cmd).unwrap();
-        reverse_commands[idx]
-    };
 
     let mut visited_coords: HashSet<Coord> = HashSet::new();
-    let mut to_visit = vec![];
-    to_visit.push(Node {
-        y: 0,
-        x: 0,
-        distance: 0,
-        directions: vec![],
-    });
-
-    loop {
-        // DRIVE PHASE
-        // Go from origin to the next node to visit
-        let current_node = to_visit.remove(0);
-        for cmd in current_node.directions.iter() {
-            program.input.push(*cmd);
-            program.run();
-        }
-        visited_coords.insert(Coord(current_node.y, current_node.x));
+    visited_coords.insert(Coord(0, 0));
+    let mut to_visit = VecDeque::new();
+    to_visit.push_back((
+        Node {
+            y: 0,
+            x: 0,
+            distance: 0,
+        },
+        program.snapshot(),
+    ));
+    let mut max_distance = 0;
+
+    while let Some((current_node, snapshot)) = to_visit.pop_front() {
+        max_distance = max_distance.max(current_node.distance);
 
-        // DISCOVERY PHASE
         // Find adjacent nodes to this one, and queue them up for visiting
         for (idx, cmd) in move_commands.iter().enumerate() {
             let dir = move_dirs[idx];
-            program.input.push(*cmd);
-            program.run();
-            let output = program.output.last().unwrap().clone();
+            let mut program = Program::from_snapshot(&snapshot);
+            program.input.push_back(*cmd);
+            program.run().unwrap();
+            let output = *program.output.last().unwrap();
 
             match output {
                 0 => {}
@@ -97,68 +82,36 @@ fn walk_maze(mut program: Program, mode: WalkMode) -> (isize, Program) {
                         _ => 0,
                     };
 
-                    let distance = current_node.distance + 1;
-
-                    let mut directions = current_node.directions.clone();
-                    directions.push(*cmd);
-                    assert_eq!(distance as usize, directions.len());
-
                     let node = Node {
                         y: current_node.y + dy,
                         x: current_node.x + dx,
-                        distance,
-                        directions,
+                        distance: current_node.distance + 1,
                     };
 
-                    // Queue up the adjacent node for search if we haven't already
-                    if !visited_coords.contains(&Coord(node.y, node.x)) {
-                        to_visit.push(node.clone());
-                    }
-
-                    // Move back to original location
-                    let reverse_command = reverse_of(cmd);
-                    program.input.push(reverse_command);
-                    program.run();
-
                     // Check if we made it to the goal; if so, return the distance. This works fine
                     // because we're doing a breadth-first search.
                     if mode == WalkMode::Oxygen && output == 2 {
-                        return (distance, program);
+                        return (node.distance, program);
+                    }
+
+                    // Queue up the adjacent node for search if we haven't already
+                    if visited_coords.insert(Coord(node.y, node.x)) {
+                        to_visit.push_back((node, program.snapshot()));
                     }
                 }
                 _ => panic!("Unsupported output: {}", output),
             }
         }
-
-        // REVERSE PHASE
-        // Go back to origin at (0,0)
-        for cmd in current_node.directions.iter().rev() {
-            program.input.push(reverse_of(cmd));
-            program.run();
-        }
-
-        if mode == WalkMode::Full && to_visit.is_empty() {
-            return (current_node.distance + 1, program);
-        }
     }
+
+    (max_distance, program)
 }
 
-#[derive(Ord, PartialOrd, Eq, PartialEq, Hash, Clone)]
+#[derive(Ord, PartialOrd, Eq, PartialEq, Hash, Clone, Debug)]
 struct Node {
     y: isize,
     x: isize,
     distance: isize,
-    directions: Vec<isize>,
-}
-
-impl Debug for Node {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "Node {{ y={}, x={}, dist={} }}",
-            self.y, self.x, self.distance
-        )
-    }
 }
 
 #[derive(Ord, PartialOrd, Eq, PartialEq, Hash, Clone, Debug)]
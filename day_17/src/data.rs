@@ -29,7 +29,7 @@ pub struct Scaffold {
 }
 
 impl Scaffold {
-    pub fn from_output(output: &Vec<isize>) -> Scaffold {
+    pub fn from_text(text: &str) -> Scaffold {
         use Node::*;
         use RoboOrientation::*;
 
@@ -40,12 +40,14 @@ impl Scaffold {
         let mut x = 0;
         let mut y = 0;
 
-        for char_code in output {
-            print!("{}", (*char_code as u8) as char);
+        for c in text.chars() {
+            print!("{}", c);
+
+            let char_code = c as isize;
 
             let coord = Coord { x, y };
 
-            match *char_code {
+            match char_code {
                 10 => {
                     x = 0;
                     y += 1;
@@ -85,7 +87,7 @@ impl Scaffold {
                 _ => panic!("Unknown char_code: {}", char_code),
             }
 
-            if *char_code != 10 {
+            if char_code != 10 {
                 x += 1;
             }
         }
@@ -1,17 +1,10 @@
-#![feature(entry_insert)]
-
-use crate::intcode::{Memory, Program};
-use crate::data::{Scaffold, Coord, Node};
+use crate::data::{Coord, Node, Scaffold};
+use intcode::{parse_memory, AsciiSession, Memory};
 
 mod data;
-mod intcode;
 
 fn main() {
-    let memory: Memory = include_str!("input.txt")
-        .trim()
-        .split(",")
-        .map(|s| s.parse().unwrap())
-        .collect();
+    let memory = parse_memory(include_str!("input.txt")).unwrap();
 
     println!("part_1: {}", part_1(memory.clone()));
     println!("part_2: {}", part_2(memory.clone()));
@@ -25,9 +18,8 @@ fn part_1(memory: Memory) -> isize {
 
     let mut alignment_param_sum = 0;
 
-    let mut program = Program::new(memory, None);
-    program.run();
-    let scaffold = Scaffold::from_output(&program.output);
+    let mut session = AsciiSession::new(memory);
+    let scaffold = Scaffold::from_text(&session.read_all().unwrap());
 
     for (coord, node) in scaffold.graph.iter() {
         let x = coord.x;
@@ -107,31 +99,24 @@ fn part_2(mut memory: Memory) -> isize {
     // from 1 to 2"
     memory[0] = 2;
 
-    // Initialize the program and run it to the first time it needs input
-    let mut program = Program::new(memory, None);
-    program.run();
-
-    // Helper function to translate commands into ASCII integers
-    let str_to_ascii = |str: &str| -> Vec<isize> {
-        str.chars().map(|c| c as isize).collect()
-    };
-
-    // Main movement routine, and movement functions A/B/C
-    program.input = str_to_ascii("A,B,A,B,C,C,B,A,B,C\n");
-    program.run();
-    program.input = str_to_ascii("L,4,R,8,L,6,L,10\n");
-    program.run();
-    program.input = str_to_ascii("L,6,R,8,R,10,L,6,L,6\n");
-    program.run();
-    program.input = str_to_ascii("L,4,L,4,L,10\n");
-    program.run();
-
-    // Continuous video feed? "y" or "n"
-    program.input = str_to_ascii("n\n");
-
-    // Run the program and return the output!
-    program.run();
-    *program.output.last().unwrap()
+    // The robot shows the scaffold, then asks for each routine in turn
+    let mut session = AsciiSession::new(memory);
+    let answers = [
+        // Main movement routine, and movement functions A/B/C
+        ("Main:\n", "A,B,A,B,C,C,B,A,B,C"),
+        ("Function A:\n", "L,4,R,8,L,6,L,10"),
+        ("Function B:\n", "L,6,R,8,R,10,L,6,L,6"),
+        ("Function C:\n", "L,4,L,4,L,10"),
+        ("Continuous video feed?\n", "n"),
+    ];
+    for (prompt, answer) in answers.iter() {
+        session.read_until(prompt).unwrap();
+        session.send_line(answer);
+    }
+
+    // Run the program to the end; the dust collected is the one value that isn't ASCII
+    session.read_all().unwrap();
+    session.values()[0]
 }
 
 #[cfg(test)]
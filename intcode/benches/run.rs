@@ -0,0 +1,90 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use intcode::{parse_memory, BlockCache, HaltReason, Program};
+use itertools::Itertools;
+
+// Every noun/verb pair, like day 2 part 2 (without stopping at the answer)
+fn day_02_noun_verb(c: &mut Criterion) {
+    let memory = parse_memory(include_str!("../../day_02/src/input.txt")).unwrap();
+
+    c.bench_function("day_02_noun_verb", |b| {
+        b.iter(|| {
+            for noun in 0..=99 {
+                for verb in 0..=99 {
+                    let mut program = Program::new(memory.clone(), None);
+                    program.memory[1] = noun;
+                    program.memory[2] = verb;
+                    let _ = black_box(program.run());
+                }
+            }
+        })
+    });
+}
+
+fn day_05_diagnostic(c: &mut Criterion) {
+    let memory = parse_memory(include_str!("../../day_05/src/input.txt")).unwrap();
+
+    c.bench_function("day_05_diagnostic", |b| {
+        b.iter(|| {
+            let mut program = Program::new(memory.clone(), Some(vec![5]));
+            assert_eq!(program.run(), Ok(HaltReason::Halted));
+            black_box(program.output)
+        })
+    });
+}
+
+// All 120 phase setting permutations through the amplifier chain, like day 7 part 1
+fn day_07_phase_permutations(c: &mut Criterion) {
+    let memory = parse_memory(include_str!("../../day_07/src/input.txt")).unwrap();
+
+    c.bench_function("day_07_phase_permutations", |b| {
+        b.iter(|| {
+            for combo in (0..=4).permutations(5) {
+                let mut signal = 0;
+                for phase_setting in combo {
+                    let mut program =
+                        Program::new(memory.clone(), Some(vec![phase_setting, signal]));
+                    assert_eq!(program.run(), Ok(HaltReason::Halted));
+                    signal = program.output[0];
+                }
+                black_box(signal);
+            }
+        })
+    });
+}
+
+fn day_09_boost(c: &mut Criterion) {
+    let memory = parse_memory(include_str!("../../day_09/src/input.txt")).unwrap();
+
+    c.bench_function("day_09_boost", |b| {
+        b.iter(|| {
+            let mut program = Program::new(memory.clone(), Some(vec![2]));
+            assert_eq!(program.run(), Ok(HaltReason::Halted));
+            black_box(program.output)
+        })
+    });
+}
+
+// The same, on the block cache engine. Part 2 spends all its time in a few blocks, so this should
+// come out well ahead of the interpreter.
+fn day_09_boost_block_cache(c: &mut Criterion) {
+    let memory = parse_memory(include_str!("../../day_09/src/input.txt")).unwrap();
+
+    c.bench_function("day_09_boost_block_cache", |b| {
+        b.iter(|| {
+            let mut program = Program::new(memory.clone(), Some(vec![2]));
+            program.block_cache = Some(BlockCache::new());
+            assert_eq!(program.run(), Ok(HaltReason::Halted));
+            black_box(program.output)
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    day_02_noun_verb,
+    day_05_diagnostic,
+    day_07_phase_permutations,
+    day_09_boost,
+    day_09_boost_block_cache
+);
+criterion_main!(benches);
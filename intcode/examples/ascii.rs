@@ -0,0 +1,20 @@
+// Talk to a text-mode Intcode program from the terminal, e.g.
+//   cargo run --example ascii -- ../day_17/src/input.txt
+use intcode::{parse_memory, AsciiSession};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = env::args()
+        .nth(1)
+        .expect("Usage: ascii <path to input.txt>");
+    let memory = parse_memory(&fs::read_to_string(path)?)?;
+
+    let mut session = AsciiSession::new(memory);
+    let stdin = io::stdin();
+    session.interact(stdin.lock(), io::stdout())?;
+
+    Ok(())
+}
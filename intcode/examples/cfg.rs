@@ -0,0 +1,15 @@
+// Write the control-flow graph of an Intcode program as Graphviz DOT, e.g.
+//   cargo run --example cfg -- ../day_17/src/input.txt | dot -Tsvg > day_17.svg
+use intcode::{parse_memory, ControlFlowGraph};
+use std::env;
+use std::error::Error;
+use std::fs;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = env::args().nth(1).expect("Usage: cfg <path to input.txt>");
+    let memory = parse_memory(&fs::read_to_string(path)?)?;
+
+    print!("{}", ControlFlowGraph::new(&memory).to_dot());
+
+    Ok(())
+}
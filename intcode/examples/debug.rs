@@ -0,0 +1,20 @@
+// Step through an Intcode program interactively, e.g.
+//   cargo run --example debug -- ../day_09/src/input.txt
+use intcode::{parse_memory, Debugger, Program};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = env::args()
+        .nth(1)
+        .expect("Usage: debug <path to input.txt>");
+    let memory = parse_memory(&fs::read_to_string(path)?)?;
+
+    let mut debugger = Debugger::new(Program::new(memory, None));
+    let stdin = io::stdin();
+    debugger.repl(stdin.lock(), io::stdout())?;
+
+    Ok(())
+}
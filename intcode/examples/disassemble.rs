@@ -0,0 +1,17 @@
+// Print an annotated listing of an Intcode program, e.g.
+//   cargo run --example disassemble -- ../day_13/src/input.txt
+use intcode::{listing, parse_memory};
+use std::env;
+use std::error::Error;
+use std::fs;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = env::args()
+        .nth(1)
+        .expect("Usage: disassemble <path to input.txt>");
+    let memory = parse_memory(&fs::read_to_string(path)?)?;
+
+    print!("{}", listing(&memory));
+
+    Ok(())
+}
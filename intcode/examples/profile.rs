@@ -0,0 +1,42 @@
+// Profile an Intcode program until it halts or runs out of input, feeding it any input values given
+// after the path, then print the report and the listing with counts, e.g.
+//   cargo run --example profile -- ../day_13/src/input.txt
+// With --flat, print the diffable flat counts instead.
+use intcode::{parse_memory, Profile, Program};
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let flat = args.first().map(String::as_str) == Some("--flat");
+    if flat {
+        args.remove(0);
+    }
+    if args.is_empty() {
+        panic!("Usage: profile [--flat] <path to input.txt> [input values...]");
+    }
+
+    let memory = parse_memory(&fs::read_to_string(&args[0])?)?;
+    let input = args[1..]
+        .iter()
+        .map(|s| s.parse())
+        .collect::<Result<_, _>>()?;
+
+    let mut program = Program::new(memory.clone(), Some(input));
+    program.profile = Some(Profile::new());
+    let halt_reason = program.run();
+    let profile = program.profile.unwrap();
+
+    if flat {
+        profile.write_flat(io::stdout())?;
+    } else {
+        println!("{:?}", halt_reason);
+        print!("{}", profile.report());
+        println!();
+        print!("{}", profile.listing(&memory));
+    }
+
+    Ok(())
+}
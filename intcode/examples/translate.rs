@@ -0,0 +1,17 @@
+// Print an Intcode program translated into a Rust module, e.g.
+//   cargo run --example translate -- ../day_09/src/input.txt > day_09.rs
+use intcode::{parse_memory, translate};
+use std::env;
+use std::error::Error;
+use std::fs;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = env::args()
+        .nth(1)
+        .expect("Usage: translate <path to input.txt>");
+    let memory = parse_memory(&fs::read_to_string(path)?)?;
+
+    print!("{}", translate(&memory));
+
+    Ok(())
+}
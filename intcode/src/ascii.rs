@@ -0,0 +1,193 @@
+use std::io;
+use std::io::{BufRead, Write};
+
+use crate::device::{AsciiInput, AsciiOutput};
+use crate::error::IntcodeError;
+use crate::program::{HaltReason, Memory, Program};
+use HaltReason::*;
+
+// A conversation with a program that talks in ASCII text, like day 17's vacuum robot: send it
+// lines, and read back what it says a line or a prompt at a time. Anything it outputs outside the
+// ASCII range, like a final answer, is kept apart in values().
+//
+//   let mut session = AsciiSession::new(memory);
+//   session.read_until("Main:\n")?;
+//   session.send_line("A,B,A");
+#[derive(Debug, Eq, PartialEq)]
+pub struct AsciiSession {
+    pub program: Program<AsciiInput, AsciiOutput>,
+    // How much of the program's output text has been read so far
+    read: usize,
+}
+
+impl AsciiSession {
+    pub fn new(memory: Memory) -> AsciiSession {
+        AsciiSession::from_program(Program::with_devices(
+            memory,
+            AsciiInput::new(),
+            AsciiOutput::new(),
+        ))
+    }
+
+    pub fn from_program(program: Program<AsciiInput, AsciiOutput>) -> AsciiSession {
+        AsciiSession { program, read: 0 }
+    }
+
+    pub fn send(&mut self, text: &str) {
+        self.program.input.push_str(text);
+    }
+
+    pub fn send_line(&mut self, line: &str) {
+        self.program.input.push_line(line);
+    }
+
+    // Run until the program outputs a whole line, and return it without its newline. If the
+    // program halts or needs input first, whatever it got out of the line is returned instead, or
+    // None if that's nothing.
+    pub fn read_line(&mut self) -> Result<Option<String>, IntcodeError> {
+        self.run_until(|unread| unread.ends_with('\n'))?;
+
+        let mut line = self.take_unread();
+        if line.ends_with('\n') {
+            line.pop();
+            return Ok(Some(line));
+        }
+        Ok(Some(line).filter(|line| !line.is_empty()))
+    }
+
+    // Run until the program's output ends with prompt, and return everything up to and including
+    // it. Stops early, returning what there was, if the program halts or needs input first.
+    pub fn read_until(&mut self, prompt: &str) -> Result<String, IntcodeError> {
+        self.run_until(|unread| unread.ends_with(prompt))?;
+        Ok(self.take_unread())
+    }
+
+    // Run until the program halts or needs input, and return all it said
+    pub fn read_all(&mut self) -> Result<String, IntcodeError> {
+        self.run_until(|_| false)?;
+        Ok(self.take_unread())
+    }
+
+    // The values output that weren't ASCII, in order
+    pub fn values(&self) -> &[isize] {
+        &self.program.output.values
+    }
+
+    // Hand the program over to a person: print what it says a line at a time, and send it each
+    // line they type, until it halts or they run out of input
+    pub fn interact<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        let mut lines = input.lines();
+        let mut values_shown = self.values().len();
+
+        loop {
+            let result = self.run_until(|unread| unread.ends_with('\n'));
+            write!(output, "{}", self.take_unread())?;
+            for value in &self.values()[values_shown..] {
+                writeln!(output, "[{}]", value)?;
+            }
+            values_shown = self.values().len();
+            output.flush()?;
+
+            match result {
+                Ok(OutputReady) => {}
+                Ok(NeedsInput) => match lines.next() {
+                    Some(line) => self.send_line(&line?),
+                    None => return Ok(()),
+                },
+                Ok(halt_reason) => return writeln!(output, "{:?}", halt_reason),
+                Err(err) => return writeln!(output, "Fault: {}", err),
+            }
+        }
+    }
+
+    // Run the program an output at a time until done returns true for the text that's not been
+    // read yet, or it stops for some other reason
+    fn run_until<F: Fn(&str) -> bool>(&mut self, done: F) -> Result<HaltReason, IntcodeError> {
+        loop {
+            let text_len = self.program.output.text.len();
+            match self.program.run_until_output()? {
+                // Values outside the ASCII range don't add to the text
+                OutputReady => {
+                    let text = &self.program.output.text;
+                    if text.len() > text_len && done(&text[self.read..]) {
+                        return Ok(OutputReady);
+                    }
+                }
+                halt_reason => return Ok(halt_reason),
+            }
+        }
+    }
+
+    fn take_unread(&mut self) -> String {
+        let unread = self.program.output.text[self.read..].to_string();
+        self.read = self.program.output.text.len();
+        unread
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use std::io::Cursor;
+
+    // Ask for a name, greet it, and output how long it was, plus 1000 to keep it out of the ASCII
+    // range; an empty name ends it
+    const GREETER: &str = "
+        ask:    OUT #78
+                OUT #97
+                OUT #109
+                OUT #101
+                OUT #63
+                OUT #10
+                ADD #1000, #0, len
+                IN c
+                EQ c, #10, eol
+                JT eol, #end
+                OUT #72
+                OUT #105
+                OUT #32
+        echo:   OUT c
+                ADD len, #1, len
+                IN c
+                EQ c, #10, eol
+                JF eol, #echo
+                OUT len
+                OUT #10
+                JT #1, #ask
+        end:    HLT
+        c:      .data 0
+        len:    .data 0
+        eol:    .data 0
+        ";
+
+    #[test]
+    fn test_ascii_session() {
+        let mut session = AsciiSession::new(assemble(GREETER).unwrap());
+        assert_eq!(session.read_until("?"), Ok("Name?".to_string()));
+        assert_eq!(session.read_line(), Ok(Some("".to_string())));
+        assert_eq!(session.read_line(), Ok(None));
+
+        session.send_line("Ada");
+        assert_eq!(session.read_line(), Ok(Some("Hi Ada".to_string())));
+        assert_eq!(session.values(), &[1003]);
+        assert_eq!(session.read_all(), Ok("Name?\n".to_string()));
+
+        session.send_line("");
+        assert_eq!(session.read_all(), Ok("".to_string()));
+        assert_eq!(session.program.halt_reason, Some(Halted));
+    }
+
+    #[test]
+    fn test_ascii_session_interact() {
+        let mut session = AsciiSession::new(assemble(GREETER).unwrap());
+        let mut output = vec![];
+        session
+            .interact(Cursor::new("Grace\n\n"), &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Name?\nHi Grace\n[1005]\nName?\nHalted\n"
+        );
+    }
+}
@@ -0,0 +1,468 @@
+use std::collections::HashMap;
+
+use crate::error::AssembleError;
+use crate::operation::Operation;
+use crate::program::{Memory, ParameterMode, ParameterUse, Program, PARAM_MODE_PLACES};
+use ParameterMode::*;
+
+// One statement per line; labels end in ':' and comments start with ';'.
+//
+//   loop:   ADD count, #-1, count   ; count -= 1
+//           JT count, #loop
+//           HLT
+//   count:  .data 10
+//
+// Operands use the disassembler's syntax (bare for position mode, '#' for immediate mode, '@' for
+// relative mode). Their values, and the values of .data directives, are integers, labels, or a
+// label plus or minus an integer, like `buffer+2`.
+pub fn assemble(source: &str) -> Result<Memory, AssembleError> {
+    let (statements, labels) = parse(source)?;
+    let mut memory = vec![];
+
+    for statement in statements {
+        match statement {
+            Statement::Instruction {
+                line,
+                operation,
+                operands,
+            } => {
+                let mut instruction = operation.opcode;
+                let mut params = vec![];
+
+                for (pat, operand) in operation.params_at.iter().zip(operands) {
+                    let (mode, value) = match operand.chars().next() {
+                        Some('#') => (ImmediateMode, &operand[1..]),
+                        Some('@') => (RelativeMode, &operand[1..]),
+                        _ => (PositionMode, operand),
+                    };
+                    if pat.1 == ParameterUse::Write && mode == ImmediateMode {
+                        return Err(AssembleError::ImmediateModeWrite {
+                            line,
+                            operand: operand.to_string(),
+                        });
+                    }
+                    instruction += Program::param_mode_digit(mode) * PARAM_MODE_PLACES[pat.0];
+                    params.push(resolve(value, line, &labels)?);
+                }
+
+                memory.push(instruction);
+                memory.append(&mut params);
+            }
+            Statement::Data { line, values } => {
+                for value in values {
+                    memory.push(resolve(value, line, &labels)?);
+                }
+            }
+        }
+    }
+
+    Ok(memory)
+}
+
+enum Statement<'a> {
+    Instruction {
+        line: usize,
+        operation: &'static Operation,
+        operands: Vec<&'a str>,
+    },
+    Data {
+        line: usize,
+        values: Vec<&'a str>,
+    },
+}
+
+type Labels<'a> = HashMap<&'a str, isize>;
+
+// Split the source into statements, and find the address every label points to along the way
+fn parse(source: &str) -> Result<(Vec<Statement<'_>>, Labels<'_>), AssembleError> {
+    let mut statements = vec![];
+    let mut labels = HashMap::new();
+    let mut address = 0;
+
+    for (idx, text) in source.lines().enumerate() {
+        let line = idx + 1;
+        let mut text = text.split(';').next().unwrap().trim();
+
+        while let Some(colon_idx) = text.find(':') {
+            let label = text[..colon_idx].trim();
+            if !is_label(label) {
+                return Err(AssembleError::InvalidLabel {
+                    line,
+                    label: label.to_string(),
+                });
+            }
+            if labels.insert(label, address).is_some() {
+                return Err(AssembleError::DuplicateLabel {
+                    line,
+                    label: label.to_string(),
+                });
+            }
+            text = text[(colon_idx + 1)..].trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (name, rest) = match text.find(char::is_whitespace) {
+            Some(space_idx) => (&text[..space_idx], text[space_idx..].trim()),
+            None => (text, ""),
+        };
+        let operands: Vec<&str> = if rest.is_empty() {
+            vec![]
+        } else {
+            rest.split(',').map(str::trim).collect()
+        };
+
+        if name.eq_ignore_ascii_case(".data") {
+            address += operands.len() as isize;
+            statements.push(Statement::Data {
+                line,
+                values: operands,
+            });
+            continue;
+        }
+
+        let operation = match Operation::from_mnemonic(name) {
+            Some(operation) => operation,
+            None => {
+                return Err(AssembleError::UnknownMnemonic {
+                    line,
+                    mnemonic: name.to_string(),
+                })
+            }
+        };
+        if operands.len() != operation.params_at.len() {
+            return Err(AssembleError::WrongOperandCount {
+                line,
+                mnemonic: name.to_string(),
+                expected: operation.params_at.len(),
+                found: operands.len(),
+            });
+        }
+
+        address += operation.size() as isize;
+        statements.push(Statement::Instruction {
+            line,
+            operation,
+            operands,
+        });
+    }
+
+    Ok((statements, labels))
+}
+
+fn is_label(label: &str) -> bool {
+    let mut chars = label.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn resolve(value: &str, line: usize, labels: &Labels) -> Result<isize, AssembleError> {
+    if let Ok(value) = value.parse() {
+        return Ok(value);
+    }
+
+    let invalid_operand = || AssembleError::InvalidOperand {
+        line,
+        operand: value.to_string(),
+    };
+
+    // Split `label+offset` or `label-offset`; there's never a sign at the start of a label
+    let (label, offset) = match value.find(&['+', '-'][..]) {
+        Some(sign_idx) => {
+            let offset = value[sign_idx..]
+                .trim_start_matches('+')
+                .parse::<isize>()
+                .map_err(|_| invalid_operand())?;
+            (value[..sign_idx].trim(), offset)
+        }
+        None => (value, 0),
+    };
+
+    if !is_label(label) {
+        return Err(invalid_operand());
+    }
+
+    match labels.get(label) {
+        Some(address) => Ok(address + offset),
+        None => Err(AssembleError::UnknownLabel {
+            line,
+            label: label.to_string(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::source;
+    use crate::program::HaltReason;
+
+    fn run(source: &str, input: Vec<isize>) -> Vec<isize> {
+        let mut program = Program::new(assemble(source).unwrap(), Some(input));
+        assert_eq!(program.run(), Ok(HaltReason::Halted));
+        program.output
+    }
+
+    #[test]
+    fn test_assemble() {
+        assert_eq!(
+            assemble(
+                "
+                ADD 9, 10, 3
+                MUL 3, 11, 0
+                HLT
+                .data 30, 40, 50
+                "
+            ),
+            Ok(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]),
+        );
+
+        // The feedback loop amplifier program from day 7
+        assert_eq!(
+            assemble(
+                "
+                        IN phase
+                        ADD phase, #-4, phase
+                loop:   IN signal
+                        MUL signal, #2, signal
+                        ADD signal, phase, signal
+                        OUT signal
+                        ADD count, #-1, count
+                        JT count, #loop
+                        HLT
+                phase:  .data 0
+                signal: .data 0
+                count:  .data 5
+                "
+            ),
+            Ok(vec![
+                3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28,
+                -1, 28, 1005, 28, 6, 99, 0, 0, 5
+            ]),
+        );
+
+        // Mnemonics are case-insensitive, labels can stack and be offset, and blank lines and
+        // comments take no space
+        assert_eq!(
+            assemble(
+                "
+                ; Print the second value in the table
+                start: main:
+                    out table+1      ; the first value is a header
+
+                    hlt
+                table: .data table-1, 42, -7
+                "
+            ),
+            Ok(vec![4, 4, 99, 2, 42, -7]),
+        );
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        use AssembleError::*;
+
+        assert_eq!(
+            assemble("HLT\nJMP #0"),
+            Err(UnknownMnemonic {
+                line: 2,
+                mnemonic: "JMP".to_string(),
+            }),
+        );
+        assert_eq!(
+            assemble("ADD 1, 2"),
+            Err(WrongOperandCount {
+                line: 1,
+                mnemonic: "ADD".to_string(),
+                expected: 3,
+                found: 2,
+            }),
+        );
+        assert_eq!(
+            assemble("OUT #1x"),
+            Err(InvalidOperand {
+                line: 1,
+                operand: "1x".to_string(),
+            }),
+        );
+        assert_eq!(
+            assemble("x: OUT #x+"),
+            Err(InvalidOperand {
+                line: 1,
+                operand: "x+".to_string(),
+            }),
+        );
+        assert_eq!(
+            assemble("IN #5"),
+            Err(ImmediateModeWrite {
+                line: 1,
+                operand: "#5".to_string(),
+            }),
+        );
+        assert_eq!(
+            assemble("2x: HLT"),
+            Err(InvalidLabel {
+                line: 1,
+                label: "2x".to_string(),
+            }),
+        );
+        assert_eq!(
+            assemble("OUT nowhere\nHLT"),
+            Err(UnknownLabel {
+                line: 1,
+                label: "nowhere".to_string(),
+            }),
+        );
+        assert_eq!(
+            assemble("a: HLT\na: HLT").unwrap_err().to_string(),
+            "Duplicate label: a (line 2)",
+        );
+    }
+
+    // One program per opcode, covering every addressing mode it supports
+    #[test]
+    fn test_assemble_run() {
+        let add = "
+                ADD #2, #3, out
+                ADD out, #1, out
+                ARB #base
+                ADD @0, @1, @2
+                OUT out
+                OUT @2
+                HLT
+        out:    .data 0
+        base:   .data 10, 20, 0
+        ";
+        assert_eq!(run(add, vec![]), vec![6, 30]);
+
+        let mul = "
+                MUL #6, #7, x
+                MUL x, #2, x
+                ARB #x
+                MUL @0, @0, @0
+                OUT x
+                HLT
+        x:      .data 0
+        ";
+        assert_eq!(run(mul, vec![]), vec![7056]);
+
+        let input = "
+                IN a
+                ARB #b
+                IN @0
+                OUT a
+                OUT b
+                HLT
+        a:      .data 0
+        b:      .data 0
+        ";
+        assert_eq!(run(input, vec![7, 8]), vec![7, 8]);
+
+        let output = "
+                OUT #5
+                OUT v
+                ARB #v
+                OUT @0
+                HLT
+        v:      .data 9
+        ";
+        assert_eq!(run(output, vec![]), vec![5, 9, 9]);
+
+        let jumps = "
+                JT #0, #fail
+                JF #1, #fail
+                JT #1, #a
+                OUT #-1
+        a:      JF zero, dest
+                OUT #-1
+        b:      ARB #vars
+                JT @1, @2
+                OUT #-1
+        c:      OUT #1
+                HLT
+        fail:   OUT #0
+                HLT
+        zero:   .data 0
+        dest:   .data b
+        vars:   .data 0, 1, c
+        ";
+        assert_eq!(run(jumps, vec![]), vec![1]);
+
+        let comparisons = "
+                LT #1, #2, r1
+                LT #2, #1, r2
+                EQ #3, #3, r3
+                EQ r1, r3, r4
+                ARB #r1
+                EQ @0, @1, @4
+                OUT r1
+                OUT r2
+                OUT r3
+                OUT r4
+                OUT r5
+                HLT
+        r1:     .data 0
+        r2:     .data 0
+        r3:     .data 0
+        r4:     .data 0
+        r5:     .data 0
+        ";
+        assert_eq!(run(comparisons, vec![]), vec![1, 0, 1, 1, 0]);
+
+        let relative_base = "
+                ARB #vars
+                ARB @0
+                OUT @0
+                ARB step
+                OUT @0
+                HLT
+        step:   .data 1
+        vars:   .data 1, 20, 30
+        ";
+        assert_eq!(run(relative_base, vec![]), vec![20, 30]);
+    }
+
+    #[test]
+    fn test_assemble_disassemble_round_trip() {
+        let source_text = "
+                IN n
+        loop:   MUL acc, n, acc
+                ADD n, #-1, n
+                JT n, #loop
+                OUT acc
+                HLT
+        n:      .data 0
+        acc:    .data 1
+        ";
+        let memory = assemble(source_text).unwrap();
+        assert_eq!(assemble(&source(&memory)), Ok(memory.clone()));
+        assert_eq!(
+            source(&memory),
+            source(&assemble(&source(&memory)).unwrap())
+        );
+
+        for input in &[
+            include_str!("../../day_02/src/input.txt"),
+            include_str!("../../day_05/src/input.txt"),
+            include_str!("../../day_07/src/input.txt"),
+            include_str!("../../day_09/src/input.txt"),
+            include_str!("../../day_11/src/input.txt"),
+            include_str!("../../day_13/src/input.txt"),
+            include_str!("../../day_15/src/input.txt"),
+            include_str!("../../day_17/src/input.txt"),
+        ] {
+            let memory: Memory = input
+                .trim()
+                .split(',')
+                .map(|s| s.parse().unwrap())
+                .collect();
+            assert_eq!(assemble(&source(&memory)), Ok(memory));
+        }
+    }
+}
@@ -0,0 +1,551 @@
+use crate::device::{InputSource, OutputSink};
+use crate::error::IntcodeError;
+use crate::memory::PagedMemory;
+use crate::operation::{Operation, HLT};
+use crate::program::{
+    write_address, Engine, Fetched, Instruction, Interpreter, ParameterMode, ParameterModes,
+    ParameterUse, Program, MAX_PARAMS,
+};
+use crate::word::Word;
+use ParameterMode::*;
+use ParameterUse::*;
+
+// Set Program::block_cache to run programs on the cached engine instead of the plain interpreter.
+// Instructions are decoded the first time they run, with their operands worked out as far as they
+// can be, and kept in basic blocks that are run straight through after that. Unless something
+// needs to see each instruction (a trace, profile, or history), they're run one after another
+// without going back through the main loop at all, which is where most of the time is saved.
+//
+// A write into a cached instruction drops it and the rest of its block, so self-modifying code runs
+// whatever is in memory, and whatever was dropped is decoded again if it runs again. Memory can
+// also be written directly between runs, which it logs, so the cache drops whatever those writes
+// hit at the start of the next run. If it can't tell what changed (because memory was replaced or
+// restored from a snapshot, or the custom opcodes changed), everything is dropped.
+//
+// Decoding costs a few times what interpreting does, so the cache pays off on programs that run
+// their code many times over, not on short runs of freshly loaded programs.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct BlockCache<W = isize> {
+    // Every cached instruction, with each block chained from its first instruction through `next`.
+    // Dropped instructions are left in place, marked free, to be reused.
+    instructions: Vec<Cached<W>>,
+    free: Vec<usize>,
+    // The first instruction of the block starting at each address
+    starts: Vec<Option<usize>>,
+    // How many cached instructions each cell is part of
+    code: Vec<u32>,
+    // The last instruction fetched, and the one after it in its block, which is usually next
+    last: Option<usize>,
+    cursor: Option<usize>,
+    // The custom opcodes everything cached was decoded with
+    custom_generation: usize,
+    // The log memory has kept of its writes since the last run
+    log_stamp: usize,
+    // Instructions decoded, and times part of a block was dropped because its code changed
+    pub decoded: usize,
+    pub invalidated: usize,
+}
+
+// The ip of a free instruction, which no instruction can really be at
+const FREE: usize = usize::MAX;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Cached<W> {
+    ip: usize,
+    instruction: Instruction,
+    operation: &'static Operation,
+    param_modes: ParameterModes,
+    params: [Param<W>; MAX_PARAMS],
+    // The instruction after it in its block, once that's been decoded
+    next: Option<usize>,
+}
+
+// A parameter, worked out as far as it can be without running the instruction. Parameters the
+// operation doesn't have are Value(0).
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Param<W> {
+    // Immediate mode reads, and the addresses position mode writes go to
+    Value(W),
+    // Position mode reads
+    Address(usize),
+    // Relative mode, by offset
+    RelativeRead(isize),
+    RelativeWrite(isize),
+}
+
+impl<W: Word> BlockCache<W> {
+    pub fn new() -> BlockCache<W> {
+        Default::default()
+    }
+
+    // How many blocks are cached
+    pub fn len(&self) -> usize {
+        self.starts.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        *self = BlockCache {
+            decoded: self.decoded,
+            invalidated: self.invalidated,
+            ..Default::default()
+        };
+    }
+
+    // Drop any cached instruction address is part of, along with the rest of its block
+    pub(crate) fn invalidate(&mut self, address: usize) {
+        if self.code.get(address).copied().unwrap_or(0) == 0 {
+            return;
+        }
+        for idx in 0..self.instructions.len() {
+            if self.instructions[idx].covers(address) {
+                self.truncate(idx);
+            }
+        }
+    }
+
+    // Drop every cached instruction memory was written over since the last run, or all of them if
+    // that isn't known or the custom opcodes aren't the ones they were decoded with
+    pub(crate) fn sync(&mut self, memory: &mut PagedMemory<W>, custom_generation: usize) {
+        let (log_stamp, writes) = memory.restart_log(self.log_stamp);
+        match writes {
+            Some(writes) if custom_generation == self.custom_generation => {
+                for address in writes {
+                    self.invalidate(address);
+                }
+            }
+            _ => {
+                self.clear();
+                self.custom_generation = custom_generation;
+            }
+        }
+        self.log_stamp = log_stamp;
+    }
+
+    // The cached instruction at ip, decoding it if need be. Instructions that would fault are never
+    // cached, and give None.
+    #[inline(always)]
+    fn locate<I: InputSource<W>, O: OutputSink<W>>(
+        &mut self,
+        program: &Program<I, O, W>,
+        ip: usize,
+    ) -> Option<usize> {
+        let idx = match (self.cursor, self.starts.get(ip)) {
+            (Some(idx), _) if self.instructions[idx].ip == ip => idx,
+            (_, Some(&Some(idx))) => idx,
+            _ => self.place(program, ip)?,
+        };
+        self.last = Some(idx);
+        self.cursor = self.instructions[idx].next;
+
+        Some(idx)
+    }
+
+    // Decode the instruction at ip when it isn't the next one cached, and no block starts there:
+    // onto the end of the block that just ran if it follows on from it, or else as a new block
+    #[inline(never)]
+    fn place<I: InputSource<W>, O: OutputSink<W>>(
+        &mut self,
+        program: &Program<I, O, W>,
+        ip: usize,
+    ) -> Option<usize> {
+        let cached = decode(program, ip)?;
+        let follows = match self.last {
+            Some(last) => self.follows(last, ip),
+            None => false,
+        };
+        let idx = self.push(cached);
+        if follows {
+            self.instructions[self.last.unwrap()].next = Some(idx);
+        } else {
+            if self.starts.len() <= ip {
+                self.starts.resize(ip + 1, None);
+            }
+            self.starts[ip] = Some(idx);
+        }
+
+        Some(idx)
+    }
+
+    // Whether the block last is the end of can run on into ip
+    fn follows(&self, last: usize, ip: usize) -> bool {
+        let cached = &self.instructions[last];
+        cached.next.is_none()
+            && cached.ip + cached.operation.size() == ip
+            && !cached.operation.is_jump()
+            && cached.operation != &HLT
+    }
+
+    fn push(&mut self, cached: Cached<W>) -> usize {
+        let end = cached.ip + cached.operation.size();
+        if self.code.len() < end {
+            self.code.resize(end, 0);
+        }
+        for n in &mut self.code[cached.ip..end] {
+            *n += 1;
+        }
+        self.decoded += 1;
+
+        match self.free.pop() {
+            Some(idx) => {
+                self.instructions[idx] = cached;
+                idx
+            }
+            None => {
+                self.instructions.push(cached);
+                self.instructions.len() - 1
+            }
+        }
+    }
+
+    // Drop instruction idx and everything after it in its block, which might be the whole block
+    fn truncate(&mut self, idx: usize) {
+        for cached in &mut self.instructions {
+            if cached.next == Some(idx) {
+                cached.next = None;
+            }
+        }
+
+        let mut next = Some(idx);
+        while let Some(idx) = next {
+            let cached = &mut self.instructions[idx];
+            next = cached.next.take();
+            for n in &mut self.code[cached.ip..cached.ip + cached.operation.size()] {
+                *n -= 1;
+            }
+            if let Some(start) = self.starts.get_mut(cached.ip) {
+                if *start == Some(idx) {
+                    *start = None;
+                }
+            }
+            cached.ip = FREE;
+            self.free.push(idx);
+        }
+        self.last = None;
+        self.cursor = None;
+        self.invalidated += 1;
+    }
+}
+
+impl<W> Cached<W> {
+    fn covers(&self, address: usize) -> bool {
+        self.ip != FREE && self.ip <= address && address < self.ip + self.operation.size()
+    }
+}
+
+impl<W: Word> Engine<W> for BlockCache<W> {
+    #[inline(always)]
+    fn fetch<I: InputSource<W>, O: OutputSink<W>>(
+        &mut self,
+        program: &Program<I, O, W>,
+    ) -> Result<Fetched<W>, IntcodeError> {
+        let cached = match self.locate(program, program.ip) {
+            Some(idx) => &self.instructions[idx],
+            // The interpreter reports the fault
+            None => return interpret(program),
+        };
+
+        let [a, b, c] = &cached.params;
+        let params = match (
+            resolve(program, a),
+            resolve(program, b),
+            resolve(program, c),
+        ) {
+            (Some(a), Some(b), Some(c)) => [a, b, c],
+            _ => return interpret(program),
+        };
+
+        Ok(Fetched {
+            instruction: cached.instruction,
+            operation: cached.operation,
+            param_modes: cached.param_modes,
+            params,
+        })
+    }
+
+    #[inline]
+    fn write(&mut self, memory: &mut PagedMemory<W>, address: usize, value: W) {
+        memory.store(address, value);
+        // Most writes are to data, which only needs this check
+        if self.code.get(address).copied().unwrap_or(0) != 0 {
+            self.invalidate(address);
+        }
+    }
+
+    // Where the cache pays off: each instruction is run as soon as it's found, with nothing in
+    // between. Anything that would fault is left for the loop to fetch again and report.
+    fn run_ahead<I: InputSource<W>, O: OutputSink<W>>(
+        &mut self,
+        program: &mut Program<I, O, W>,
+        max_steps: usize,
+    ) -> usize {
+        let mut steps = 0;
+
+        while steps < max_steps && program.ip < program.memory.len() {
+            let ip = program.ip;
+            let last = self.last;
+            let idx = match self.locate(program, ip) {
+                Some(idx) => idx,
+                None => break,
+            };
+            let cached = &self.instructions[idx];
+            let opcode = cached.operation.opcode;
+            let [a, b, c] = &cached.params;
+            let params = match (
+                resolve(program, a),
+                resolve(program, b),
+                resolve(program, c),
+            ) {
+                (Some(a), Some(b), Some(c)) => Some([a, b, c]),
+                _ => None,
+            };
+
+            let done = match (opcode, params) {
+                // ADD, MULTIPLY
+                (1, Some([a, b, c])) | (2, Some([a, b, c])) => {
+                    let value = if opcode == 1 {
+                        a.checked_add(&b)
+                    } else {
+                        a.checked_mul(&b)
+                    };
+                    value.map(|value| {
+                        self.write(&mut program.memory, write_address(&c), value);
+                        ip + 4
+                    })
+                }
+
+                // JUMP-IF-TRUE, JUMP-IF-FALSE
+                (5, Some([a, b, _])) | (6, Some([a, b, _])) => {
+                    if (a != W::default()) == (opcode == 5) {
+                        match b.to_isize() {
+                            Some(target) if target >= 0 => Some(target as usize),
+                            _ => None,
+                        }
+                    } else {
+                        Some(ip + 3)
+                    }
+                }
+
+                // LESS-THAN, EQUALS
+                (7, Some([a, b, c])) | (8, Some([a, b, c])) => {
+                    let test = if opcode == 7 { a < b } else { a == b };
+                    let value = W::from_isize(if test { 1 } else { 0 });
+                    self.write(&mut program.memory, write_address(&c), value);
+                    Some(ip + 4)
+                }
+
+                // ADJUST-RELATIVE-BASE
+                (9, Some([a, _, _])) => a
+                    .to_isize()
+                    .and_then(|delta| (program.relative_base as isize).checked_add(delta))
+                    .map(|relative_base| {
+                        program.relative_base = relative_base as usize;
+                        ip + 2
+                    }),
+
+                // Left for the loop
+                _ => None,
+            };
+
+            match done {
+                Some(next_ip) => {
+                    program.ip = next_ip;
+                    program.steps += 1;
+                    steps += 1;
+                }
+                // Put back as if it had never been looked at, so the loop finds it next
+                None => {
+                    self.last = last;
+                    self.cursor = Some(idx);
+                    break;
+                }
+            }
+        }
+
+        steps
+    }
+}
+
+// Faults are rare, so this is kept out of the way of the cached path
+#[inline(never)]
+fn interpret<I: InputSource<W>, O: OutputSink<W>, W: Word>(
+    program: &Program<I, O, W>,
+) -> Result<Fetched<W>, IntcodeError> {
+    Interpreter.fetch(program)
+}
+
+// None if a relative address comes out negative, which the interpreter reports
+#[inline(always)]
+fn resolve<I: InputSource<W>, O: OutputSink<W>, W: Word>(
+    program: &Program<I, O, W>,
+    param: &Param<W>,
+) -> Option<W> {
+    let relative = |offset: isize| match (program.relative_base as isize).checked_add(offset) {
+        Some(address) if address >= 0 => Some(address),
+        _ => None,
+    };
+
+    match param {
+        Param::Value(value) => Some(value.clone()),
+        Param::Address(address) => Some(program.read_mem(*address)),
+        Param::RelativeRead(offset) => {
+            relative(*offset).map(|address| program.read_mem(address as usize))
+        }
+        Param::RelativeWrite(offset) => relative(*offset).map(W::from_isize),
+    }
+}
+
+// The instruction at ip, unless it would fault whatever the relative base is
+fn decode<I: InputSource<W>, O: OutputSink<W>, W: Word>(
+    program: &Program<I, O, W>,
+    ip: usize,
+) -> Option<Cached<W>> {
+    let memory = &program.memory;
+    let instruction = memory.read(ip).to_isize()?;
+    let opcode = Program::parse_opcode(instruction);
+    let operation = Operation::from_opcode(opcode).or_else(|| program.custom_operation(opcode))?;
+    let param_modes = Program::parse_param_modes(instruction, operation.params_at.len()).ok()?;
+
+    let mut params = [
+        Param::Value(W::default()),
+        Param::Value(W::default()),
+        Param::Value(W::default()),
+    ];
+    for pat in operation.params_at {
+        let operand = memory.read(ip + 1 + pat.0);
+        params[pat.0] = match (param_modes[pat.0], pat.1) {
+            (PositionMode, Read) => Param::Address(address(&operand)?),
+            (PositionMode, Write) => {
+                address(&operand)?;
+                Param::Value(operand)
+            }
+            (ImmediateMode, Read) => Param::Value(operand),
+            (ImmediateMode, Write) => return None,
+            (RelativeMode, Read) => Param::RelativeRead(operand.to_isize()?),
+            (RelativeMode, Write) => Param::RelativeWrite(operand.to_isize()?),
+        };
+    }
+
+    Some(Cached {
+        ip,
+        instruction,
+        operation,
+        param_modes,
+        params,
+        next: None,
+    })
+}
+
+fn address<W: Word>(operand: &W) -> Option<usize> {
+    match operand.to_isize() {
+        Some(address) if address >= 0 => Some(address as usize),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::program::{HaltReason::*, Program};
+
+    fn cached(memory: Vec<isize>, input: Option<Vec<isize>>) -> Program {
+        let mut program = Program::new(memory, input);
+        program.block_cache = Some(BlockCache::new());
+        program
+    }
+
+    // Count down from the input
+    const COUNTDOWN: &str = "
+                IN x            ; 0
+        loop:   ADD x, #-1, x   ; 2
+                JT x, #loop     ; 6
+                HLT             ; 9
+        x:      .data 0         ; 10
+    ";
+
+    #[test]
+    fn test_block_cache() {
+        // The loop is decoded once however long it runs
+        let memory = assemble(COUNTDOWN).unwrap();
+        let mut program = cached(memory, Some(vec![1000]));
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.steps, 2002);
+        let block_cache = program.block_cache.unwrap();
+        assert_eq!((block_cache.len(), block_cache.decoded), (3, 6));
+        assert_eq!(block_cache.invalidated, 0);
+    }
+
+    #[test]
+    fn test_block_cache_limits() {
+        // Running ahead stops where the step limit says, as the interpreter does
+        let memory = assemble(COUNTDOWN).unwrap();
+        let mut program = cached(memory.clone(), Some(vec![1000]));
+        let mut interpreted = Program::new(memory, Some(vec![1000]));
+        assert_eq!(program.run_for(1001), Ok(StepLimitReached));
+        assert_eq!(interpreted.run_for(1001), Ok(StepLimitReached));
+        assert_eq!(
+            (program.ip, program.steps, program.read_mem(10)),
+            (interpreted.ip, interpreted.steps, interpreted.read_mem(10)),
+        );
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.steps, 2002);
+    }
+
+    #[test]
+    fn test_block_cache_self_modifying() {
+        // The loop rewrites its own OUT's operand, so each time round outputs something new
+        let memory = assemble(
+            "
+            loop:   OUT #1          ; 0
+                    ADD 1, #1, 1    ; 2
+                    LT 1, #4, x     ; 6
+                    JT x, #loop     ; 10
+                    HLT             ; 13
+            x:      .data 0         ; 14
+            ",
+        )
+        .unwrap();
+        let mut program = cached(memory, None);
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.output, vec![1, 2, 3]);
+        assert_eq!(program.block_cache.unwrap().invalidated, 3);
+
+        // Code changed between runs is picked up too, and only the rest of the block from there is
+        // decoded again
+        let mut program = cached(vec![3, 7, 104, 5, 1105, 1, 0, 0], Some(vec![1]));
+        assert_eq!(program.run(), Ok(NeedsInput));
+        assert_eq!(program.output, vec![5]);
+        program.memory[3] = 6;
+        program.input.push_back(2);
+        assert_eq!(program.run(), Ok(NeedsInput));
+        assert_eq!(program.output, vec![5, 6]);
+        let block_cache = program.block_cache.as_ref().unwrap();
+        assert_eq!((block_cache.invalidated, block_cache.decoded), (1, 5));
+    }
+
+    #[test]
+    fn test_block_cache_memory_replaced() {
+        let mut program = cached(vec![3, 7, 104, 5, 1105, 1, 0, 0], Some(vec![1]));
+        assert_eq!(program.run(), Ok(NeedsInput));
+
+        // Memory loaded from elsewhere can't be checked against what was cached from the old one
+        program.memory = PagedMemory::from(vec![3, 7, 104, 6, 1105, 1, 0, 0]);
+        program.input.push_back(2);
+        assert_eq!(program.run(), Ok(NeedsInput));
+        assert_eq!(program.block_cache.as_ref().unwrap().decoded, 6);
+
+        // Nor can memory written more times than are logged
+        for address in 100..2000 {
+            program.memory[address] = 1;
+        }
+        program.memory[3] = 7;
+        program.input.push_back(3);
+        assert_eq!(program.run(), Ok(NeedsInput));
+        assert_eq!(program.output, vec![5, 6, 7]);
+        assert_eq!(program.block_cache.as_ref().unwrap().decoded, 9);
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::disassembler::{decode_at, Line, Operand};
+use crate::operation::{HLT, JT};
+use crate::program::{Memory, ParameterMode, ParameterUse};
+use ParameterMode::*;
+
+// How control leaves the end of a basic block
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Exit {
+    // Into the block starting right after this one
+    Fallthrough(usize),
+    // JT/JF whose condition is immediate, so it always jumps
+    Jump(usize),
+    // JT/JF that either jumps to target or carries on to fallthrough
+    Branch { target: usize, fallthrough: usize },
+    // A jump through a position or relative mode operand, which can't be followed without running
+    // the program. Conditional ones may also carry on to fallthrough.
+    Computed { fallthrough: Option<usize> },
+    // HLT, or running off the end of memory
+    Halt,
+    // Something run() would fault on
+    Invalid,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BasicBlock {
+    pub start: usize,
+    // One past the last cell of the last line
+    pub end: usize,
+    pub lines: Vec<Line>,
+    pub exit: Exit,
+}
+
+// Control flow recovered from memory without running it. Code is found by following every path
+// from address 0, and from anything that looks like a return address: an immediate operand naming
+// the address right after a jump (which is how a call saves where to come back to). Anything else
+// is taken to be data.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ControlFlowGraph {
+    // By start address
+    pub blocks: BTreeMap<usize, BasicBlock>,
+    // Addresses of jumps whose targets are computed at runtime
+    pub computed_jumps: Vec<usize>,
+    // (address of the instruction, address written) for position mode writes into code. Writes
+    // through relative mode operands can't be resolved statically and aren't checked.
+    pub code_writes: Vec<(usize, usize)>,
+}
+
+impl Exit {
+    pub fn successors(&self) -> Vec<usize> {
+        match *self {
+            Exit::Fallthrough(next) | Exit::Jump(next) => vec![next],
+            Exit::Branch {
+                target,
+                fallthrough,
+            } => vec![target, fallthrough],
+            Exit::Computed { fallthrough } => fallthrough.into_iter().collect(),
+            Exit::Halt | Exit::Invalid => vec![],
+        }
+    }
+}
+
+impl ControlFlowGraph {
+    pub fn new(memory: &Memory) -> ControlFlowGraph {
+        let mut lines = BTreeMap::new();
+        let mut leaders = BTreeSet::new();
+        let mut roots = vec![0];
+
+        loop {
+            explore(memory, roots, &mut lines, &mut leaders);
+
+            let immediates: HashSet<isize> = lines
+                .values()
+                .flat_map(operands)
+                .filter(|operand| operand.mode == ImmediateMode)
+                .map(|operand| operand.value)
+                .collect();
+            roots = lines
+                .values()
+                .filter(|line| is_jump(line))
+                .map(end)
+                .filter(|after| !lines.contains_key(after) && *after < memory.len())
+                .filter(|after| immediates.contains(&(*after as isize)))
+                .collect();
+
+            if roots.is_empty() {
+                break;
+            }
+        }
+
+        let code_cells: HashSet<usize> = lines
+            .values()
+            .flat_map(|line| line.address()..end(line))
+            .collect();
+        let mut computed_jumps = vec![];
+        let mut code_writes = vec![];
+        for line in lines.values() {
+            if is_jump(line) && line.jump_target().is_none() {
+                computed_jumps.push(line.address());
+            }
+            for operand in operands(line) {
+                if operand.param_use == ParameterUse::Write
+                    && operand.mode == PositionMode
+                    && code_cells.contains(&(operand.value as usize))
+                {
+                    code_writes.push((line.address(), operand.value as usize));
+                }
+            }
+        }
+
+        ControlFlowGraph {
+            blocks: blocks(memory, &lines, &leaders),
+            computed_jumps,
+            code_writes,
+        }
+    }
+
+    // A Graphviz digraph with one node per block, listing its lines. Blocks ending in a computed
+    // jump are red, and lines that write into code are marked.
+    pub fn to_dot(&self) -> String {
+        let writers: HashSet<usize> = self.code_writes.iter().map(|(ip, _)| *ip).collect();
+        let mut dot =
+            "digraph intcode {\n    node [shape=box, fontname=\"monospace\"];\n".to_string();
+
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for line in &block.lines {
+                label.push_str(&format!("{}: {}", line.address(), line));
+                if writers.contains(&line.address()) {
+                    label.push_str(" ; writes code");
+                }
+                label.push_str("\\l");
+            }
+            let color = match block.exit {
+                Exit::Computed { .. } => ", color=red",
+                Exit::Invalid => ", color=gray",
+                _ => "",
+            };
+            dot.push_str(&format!(
+                "    b{} [label=\"{}\"{}];\n",
+                block.start, label, color
+            ));
+        }
+
+        for block in self.blocks.values() {
+            let edges = match block.exit {
+                Exit::Branch {
+                    target,
+                    fallthrough,
+                } => vec![
+                    (target, " [label=\"jump\"]"),
+                    (fallthrough, " [label=\"next\"]"),
+                ],
+                exit => exit
+                    .successors()
+                    .into_iter()
+                    .map(|next| (next, ""))
+                    .collect(),
+            };
+            for (next, attributes) in edges {
+                if self.blocks.contains_key(&next) {
+                    dot.push_str(&format!(
+                        "    b{} -> b{}{};\n",
+                        block.start, next, attributes
+                    ));
+                }
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// Decode everything reachable from roots, noting where blocks have to start
+fn explore(
+    memory: &Memory,
+    mut roots: Vec<usize>,
+    lines: &mut BTreeMap<usize, Line>,
+    leaders: &mut BTreeSet<usize>,
+) {
+    leaders.extend(&roots);
+
+    while let Some(address) = roots.pop() {
+        if address >= memory.len() || lines.contains_key(&address) {
+            continue;
+        }
+
+        let line = decode_at(memory, address);
+        let exit = exit(memory, &line);
+        if is_jump(&line) {
+            leaders.extend(exit.successors());
+        }
+        roots.extend(exit.successors());
+        lines.insert(address, line);
+    }
+}
+
+// Split the decoded lines into blocks at every leader, jump, and gap
+fn blocks(
+    memory: &Memory,
+    lines: &BTreeMap<usize, Line>,
+    leaders: &BTreeSet<usize>,
+) -> BTreeMap<usize, BasicBlock> {
+    let mut blocks = BTreeMap::new();
+    let mut current: Vec<Line> = vec![];
+
+    for line in lines.values() {
+        let continues = match current.last() {
+            Some(last) => {
+                end(last) == line.address()
+                    && !leaders.contains(&line.address())
+                    && exit(memory, last) == Exit::Fallthrough(line.address())
+            }
+            None => false,
+        };
+        if !continues && !current.is_empty() {
+            let block = block(memory, current);
+            blocks.insert(block.start, block);
+            current = vec![];
+        }
+        current.push(line.clone());
+    }
+    if !current.is_empty() {
+        let block = block(memory, current);
+        blocks.insert(block.start, block);
+    }
+
+    blocks
+}
+
+fn block(memory: &Memory, lines: Vec<Line>) -> BasicBlock {
+    let last = lines.last().unwrap();
+    BasicBlock {
+        start: lines[0].address(),
+        end: end(last),
+        exit: exit(memory, last),
+        lines,
+    }
+}
+
+// How control leaves a single line
+fn exit(memory: &Memory, line: &Line) -> Exit {
+    let operation = match line {
+        Line::Instruction { operation, .. } => *operation,
+        Line::Data { .. } => return Exit::Invalid,
+    };
+    let next = end(line);
+    let fallthrough = if next < memory.len() {
+        Exit::Fallthrough(next)
+    } else {
+        Exit::Halt
+    };
+
+    if operation == &HLT {
+        return Exit::Halt;
+    }
+    if !operation.is_jump() {
+        return fallthrough;
+    }
+
+    // An immediate condition decides the jump once and for all
+    let condition = &operands(line)[0];
+    let always = match (condition.mode, condition.value) {
+        (ImmediateMode, value) => Some((value != 0) == (operation == &JT)),
+        _ => None,
+    };
+
+    match (always, line.jump_target()) {
+        (Some(false), _) => fallthrough,
+        (Some(true), Some(target)) => Exit::Jump(target),
+        (Some(true), None) => Exit::Computed { fallthrough: None },
+        (None, Some(target)) if next < memory.len() => Exit::Branch {
+            target,
+            fallthrough: next,
+        },
+        (None, Some(target)) => Exit::Jump(target),
+        (None, None) => Exit::Computed {
+            fallthrough: Some(next).filter(|next| *next < memory.len()),
+        },
+    }
+}
+
+fn is_jump(line: &Line) -> bool {
+    match line {
+        Line::Instruction { operation, .. } => operation.is_jump(),
+        Line::Data { .. } => false,
+    }
+}
+
+fn operands(line: &Line) -> &[Operand] {
+    match line {
+        Line::Instruction { operands, .. } => operands,
+        Line::Data { .. } => &[],
+    }
+}
+
+fn end(line: &Line) -> usize {
+    line.address() + line.words().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_control_flow_graph() {
+        let memory = assemble(
+            "
+                    IN x                ; 0
+            loop:   JF x, #done         ; 2
+                    ADD x, #-1, x       ; 5
+                    JT #1, #loop        ; 9
+            done:   ADD #0, #99, patch  ; 12
+                    JT x, @0            ; 16
+            patch:  HLT                 ; 19
+            x:      .data 0             ; 20
+            ",
+        )
+        .unwrap();
+        let graph = ControlFlowGraph::new(&memory);
+
+        let exits: Vec<(usize, usize, Exit)> = graph
+            .blocks
+            .values()
+            .map(|block| (block.start, block.end, block.exit))
+            .collect();
+        assert_eq!(
+            exits,
+            vec![
+                (0, 2, Exit::Fallthrough(2)),
+                (
+                    2,
+                    5,
+                    Exit::Branch {
+                        target: 12,
+                        fallthrough: 5
+                    }
+                ),
+                (5, 12, Exit::Jump(2)),
+                (
+                    12,
+                    19,
+                    Exit::Computed {
+                        fallthrough: Some(19)
+                    }
+                ),
+                (19, 20, Exit::Halt),
+            ],
+        );
+        assert_eq!(graph.blocks[&5].lines.len(), 2);
+        assert_eq!(graph.computed_jumps, vec![16]);
+        assert_eq!(graph.code_writes, vec![(12, 19)]);
+
+        assert_eq!(
+            graph.to_dot(),
+            [
+                "digraph intcode {",
+                "    node [shape=box, fontname=\"monospace\"];",
+                "    b0 [label=\"0: IN 20\\l\"];",
+                "    b2 [label=\"2: JF 20, #12\\l\"];",
+                "    b5 [label=\"5: ADD 20, #-1, 20\\l9: JT #1, #2\\l\"];",
+                "    b12 [label=\"12: ADD #0, #99, 19 ; writes code\\l16: JT 20, @0\\l\", color=red];",
+                "    b19 [label=\"19: HLT\\l\"];",
+                "    b0 -> b2;",
+                "    b2 -> b12 [label=\"jump\"];",
+                "    b2 -> b5 [label=\"next\"];",
+                "    b5 -> b2;",
+                "    b12 -> b19;",
+                "}",
+                "",
+            ]
+            .join("\n"),
+        );
+    }
+
+    #[test]
+    fn test_control_flow_graph_calls() {
+        // A call saves its return address, which is only ever jumped to through memory
+        let memory = assemble(
+            "
+                    ADD #ret, #0, r     ; 0
+                    JT #1, #func        ; 4
+            ret:    HLT                 ; 7
+            func:   JF #0, r            ; 8
+                    ADD #1, #1, 0       ; 11
+            r:      .data 0             ; 15
+            ",
+        )
+        .unwrap();
+        let graph = ControlFlowGraph::new(&memory);
+
+        let exits: Vec<(usize, Exit)> = graph
+            .blocks
+            .values()
+            .map(|block| (block.start, block.exit))
+            .collect();
+        assert_eq!(
+            exits,
+            vec![
+                (0, Exit::Jump(8)),
+                (7, Exit::Halt),
+                (8, Exit::Computed { fallthrough: None }),
+            ],
+        );
+        assert_eq!(graph.computed_jumps, vec![8]);
+        assert_eq!(graph.code_writes, vec![]);
+    }
+}
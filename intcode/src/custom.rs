@@ -0,0 +1,310 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::device::{InputSource, OutputSink};
+use crate::error::IntcodeError;
+use crate::operation::Operation;
+use crate::program::{
+    write_address, Engine, Instruction, Opcode, ParameterUse, Program, MAX_PARAMS,
+};
+use crate::word::Word;
+use ParameterUse::*;
+
+// Set Program::custom_opcodes to give the VM opcodes of its own on top of the built-in ones, e.g.
+// one that prints a value while debugging, or a call out to the host. Each is an Operation, decoded
+// with the same parameter modes as the built-in ones, and a handler that's given the values of its
+// Read params and fills in its Write param, which starts out holding what's at the address written:
+//
+//   const PRN: Operation = Operation {
+//       opcode: 42,
+//       mnemonic: "PRN",
+//       params_at: &[ParamAt(0, Read)],
+//   };
+//   custom_opcodes.register(&PRN, |reads, _| println!("{}", reads[0]));
+//
+// Custom instructions always carry on to the next one; they can't jump, pause, or halt.
+pub struct CustomOpcodes<W = isize> {
+    opcodes: BTreeMap<Opcode, (&'static Operation, Handler<W>)>,
+    // Different for every set of opcodes, and every change to one, so a block cache can tell when
+    // what it decoded is out of date
+    generation: usize,
+}
+
+type Handler<W> = Box<dyn FnMut(&[W], &mut [W]) + Send>;
+
+// 0 is left for having no custom opcodes at all
+static GENERATIONS: AtomicUsize = AtomicUsize::new(1);
+
+fn next_generation() -> usize {
+    GENERATIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+impl<W: Word> CustomOpcodes<W> {
+    pub fn new() -> CustomOpcodes<W> {
+        CustomOpcodes {
+            opcodes: BTreeMap::new(),
+            generation: next_generation(),
+        }
+    }
+
+    // Add an opcode, or replace the handler of one already added. Panics if the opcode is built in
+    // or isn't two digits, or the operation takes params that instructions can't encode: they have
+    // to be numbered 0, 1, 2... in order, and like the built-in ones, write at most once, so they
+    // can be undone.
+    pub fn register<F>(
+        &mut self,
+        operation: &'static Operation,
+        handler: F,
+    ) -> &mut CustomOpcodes<W>
+    where
+        F: FnMut(&[W], &mut [W]) + Send + 'static,
+    {
+        let opcode = operation.opcode;
+        assert!(
+            (0..100).contains(&opcode),
+            "Opcode {} doesn't fit in two digits",
+            opcode
+        );
+        assert!(
+            Operation::from_opcode(opcode).is_none(),
+            "Opcode {} is built in",
+            opcode
+        );
+        assert!(
+            operation.params_at.len() <= MAX_PARAMS
+                && operation
+                    .params_at
+                    .iter()
+                    .enumerate()
+                    .all(|(idx, pat)| pat.0 == idx),
+            "{} params have to be numbered 0 to {} in order",
+            operation.mnemonic,
+            MAX_PARAMS - 1
+        );
+        assert!(
+            operation
+                .params_at
+                .iter()
+                .filter(|pat| pat.1 == Write)
+                .count()
+                <= 1,
+            "{} writes more than once",
+            operation.mnemonic
+        );
+
+        self.opcodes.insert(opcode, (operation, Box::new(handler)));
+        self.generation = next_generation();
+        self
+    }
+
+    pub fn operation(&self, opcode: Opcode) -> Option<&'static Operation> {
+        self.opcodes.get(&opcode).map(|(operation, _)| *operation)
+    }
+
+    pub fn len(&self) -> usize {
+        self.opcodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.opcodes.is_empty()
+    }
+}
+
+impl<W: Word> Default for CustomOpcodes<W> {
+    fn default() -> CustomOpcodes<W> {
+        CustomOpcodes::new()
+    }
+}
+
+// Handlers can't be looked into, so custom opcodes are shown, and compared, by their operations
+impl<W> Debug for CustomOpcodes<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.opcodes.values().map(|(operation, _)| operation))
+            .finish()
+    }
+}
+
+impl<W> PartialEq for CustomOpcodes<W> {
+    fn eq(&self, other: &CustomOpcodes<W>) -> bool {
+        self.opcodes.len() == other.opcodes.len()
+            && self
+                .opcodes
+                .values()
+                .zip(other.opcodes.values())
+                .all(|((a, _), (b, _))| a == b)
+    }
+}
+
+impl<W> Eq for CustomOpcodes<W> {}
+
+impl<I: InputSource<W>, O: OutputSink<W>, W: Word> Program<I, O, W> {
+    pub(crate) fn custom_operation(&self, opcode: Opcode) -> Option<&'static Operation> {
+        match &self.custom_opcodes {
+            Some(custom_opcodes) => custom_opcodes.operation(opcode),
+            None => None,
+        }
+    }
+
+    // Which custom opcodes instructions are decoded with, for the block cache
+    pub(crate) fn custom_generation(&self) -> usize {
+        match &self.custom_opcodes {
+            Some(custom_opcodes) => custom_opcodes.generation,
+            None => 0,
+        }
+    }
+
+    // Run the handler of a custom instruction that's already been decoded
+    #[cold]
+    pub(crate) fn execute_custom<E: Engine<W>>(
+        &mut self,
+        engine: &mut E,
+        instruction: Instruction,
+        operation: &'static Operation,
+        params: &[W; MAX_PARAMS],
+    ) -> Result<(), IntcodeError> {
+        let mut reads = vec![];
+        let mut write = None;
+        for pat in operation.params_at {
+            match pat.1 {
+                Read => reads.push(params[pat.0].clone()),
+                Write => write = Some(write_address(&params[pat.0])),
+            }
+        }
+        let mut writes: Vec<W> = write
+            .iter()
+            .map(|&address| self.read_mem(address))
+            .collect();
+
+        let handler = match &mut self.custom_opcodes {
+            Some(custom_opcodes) => custom_opcodes.opcodes.get_mut(&operation.opcode),
+            None => None,
+        };
+        match handler {
+            Some((_, handler)) => handler(&reads, &mut writes),
+            // The block cache is synced with the custom opcodes before each run, so it should never
+            // hand over an instruction that's been taken away since it was decoded
+            None => {
+                return Err(IntcodeError::UnknownOpcode {
+                    ip: self.ip,
+                    instruction,
+                })
+            }
+        }
+
+        if let (Some(address), Some(value)) = (write, writes.pop()) {
+            self.store(engine, address, value);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_cache::BlockCache;
+    use crate::error::IntcodeError;
+    use crate::program::{HaltReason::*, ParamAt};
+    use std::sync::{Arc, Mutex};
+
+    const PRN: Operation = Operation {
+        opcode: 42,
+        mnemonic: "PRN",
+        params_at: &[ParamAt(0, Read)],
+    };
+
+    const GCD: Operation = Operation {
+        opcode: 43,
+        mnemonic: "GCD",
+        params_at: &[ParamAt(0, Read), ParamAt(1, Read), ParamAt(2, Write)],
+    };
+
+    #[test]
+    fn test_custom_opcodes() {
+        // ARB #3; GCD #84, @10, 12; PRN 12; OUT 12; HLT
+        let memory = vec![109, 3, 2143, 84, 10, 12, 42, 12, 4, 12, 99, 0, 0, 126];
+
+        for &cached in &[false, true] {
+            let printed = Arc::new(Mutex::new(vec![]));
+            let mut custom_opcodes = CustomOpcodes::new();
+            {
+                let printed = printed.clone();
+                custom_opcodes
+                    .register(&PRN, move |reads, _| printed.lock().unwrap().push(reads[0]))
+                    .register(&GCD, |reads, writes| {
+                        let (mut a, mut b) = (reads[0], reads[1]);
+                        while b != 0 {
+                            let r = a % b;
+                            a = b;
+                            b = r;
+                        }
+                        writes[0] = a;
+                    });
+            }
+
+            let mut program = Program::new(memory.clone(), None);
+            program.custom_opcodes = Some(custom_opcodes);
+            if cached {
+                program.block_cache = Some(BlockCache::new());
+            }
+            assert_eq!(program.run(), Ok(Halted));
+            assert_eq!(program.output, vec![42]);
+            assert_eq!(*printed.lock().unwrap(), vec![42]);
+            assert_eq!(program.steps, 5);
+        }
+
+        // Without them, they're unknown as ever
+        assert_eq!(
+            Program::new(memory, None).run(),
+            Err(IntcodeError::UnknownOpcode {
+                ip: 2,
+                instruction: 2143,
+            }),
+        );
+    }
+
+    #[test]
+    fn test_custom_opcodes_changed() {
+        // IN 7; PRN 7; OUT 7; HLT
+        let memory = vec![3, 7, 42, 7, 4, 7, 99, 0];
+        let mut custom_opcodes = CustomOpcodes::new();
+        custom_opcodes.register(&PRN, |_, _| {});
+
+        let mut program = Program::new(memory, Some(vec![5, 6, 7]));
+        program.custom_opcodes = Some(custom_opcodes);
+        program.block_cache = Some(BlockCache::new());
+        assert_eq!(program.run(), Ok(Halted));
+
+        // The cached PRN goes along with its handler
+        program.custom_opcodes = None;
+        program.ip = 0;
+        assert_eq!(
+            program.run(),
+            Err(IntcodeError::UnknownOpcode {
+                ip: 2,
+                instruction: 42,
+            }),
+        );
+
+        // And an opcode given a different operation is decoded afresh: 42 7 is now SET 7
+        const SET: Operation = Operation {
+            opcode: 42,
+            mnemonic: "SET",
+            params_at: &[ParamAt(0, Write)],
+        };
+        let mut custom_opcodes = CustomOpcodes::new();
+        custom_opcodes.register(&SET, |_, writes| writes[0] = 9);
+        program.custom_opcodes = Some(custom_opcodes);
+        program.ip = 0;
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.output, vec![5, 9]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Opcode 4 is built in")]
+    fn test_custom_opcodes_built_in() {
+        CustomOpcodes::<isize>::new().register(&Operation { opcode: 4, ..PRN }, |_, _| {});
+    }
+}
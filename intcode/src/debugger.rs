@@ -0,0 +1,410 @@
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::io;
+use std::io::{BufRead, Write};
+
+use crate::disassembler::decode_in;
+use crate::error::IntcodeError;
+use crate::history::History;
+use crate::program::{HaltReason, Program};
+use HaltReason::*;
+
+// How many of the most recent output values the REPL shows
+const RECENT_OUTPUT_LEN: usize = 10;
+
+// How many instructions can be stepped back over, unless the program came with its own history
+const HISTORY_LEN: usize = 100_000;
+
+// The most values one x command shows
+const MAX_MEM_LEN: usize = 1000;
+
+pub struct Debugger {
+    pub program: Program,
+    pub breakpoints: BTreeSet<usize>,
+    pub watchpoints: BTreeSet<usize>,
+}
+
+// Why the debugger handed control back
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Stop {
+    Breakpoint(usize),
+    Watchpoint {
+        address: usize,
+        old: isize,
+        new: isize,
+    },
+    Halt(HaltReason),
+}
+
+impl Debugger {
+    pub fn new(mut program: Program) -> Debugger {
+        if program.history.is_none() {
+            program.history = Some(History::new(HISTORY_LEN));
+        }
+
+        Debugger {
+            program,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+        }
+    }
+
+    // Execute one instruction, stopping early only if it halts, needs input, or hits a watchpoint
+    pub fn step(&mut self) -> Result<Stop, IntcodeError> {
+        let watched: Vec<(usize, isize)> = self
+            .watchpoints
+            .iter()
+            .map(|&address| (address, self.program.read_mem(address)))
+            .collect();
+
+        match self.program.step()? {
+            StepLimitReached | OutputReady => {}
+            halt_reason => return Ok(Stop::Halt(halt_reason)),
+        }
+
+        for (address, old) in watched {
+            let new = self.program.read_mem(address);
+            if new != old {
+                return Ok(Stop::Watchpoint { address, old, new });
+            }
+        }
+
+        Ok(Stop::Halt(StepLimitReached))
+    }
+
+    // Keep stepping until reaching a breakpoint, a watched address changes, or the program halts
+    pub fn resume(&mut self) -> Result<Stop, IntcodeError> {
+        loop {
+            match self.step()? {
+                Stop::Halt(StepLimitReached) => {}
+                stop => return Ok(stop),
+            }
+
+            if self.breakpoints.contains(&self.program.ip) {
+                return Ok(Stop::Breakpoint(self.program.ip));
+            }
+        }
+    }
+
+    // The instruction at ip, disassembled
+    pub fn current_instruction(&self) -> String {
+        if self.program.ip >= self.program.memory.len() {
+            return format!("{:>5}  <end of memory>", self.program.ip);
+        }
+
+        let line = decode_in(&self.program.memory, self.program.ip);
+        match line.annotation() {
+            Some(annotation) => format!(
+                "{:>5}  {:<20} ; {}",
+                line.address(),
+                line.to_string(),
+                annotation
+            ),
+            None => format!("{:>5}  {}", line.address(), line),
+        }
+    }
+
+    pub fn state(&self) -> String {
+        let output = &self.program.output;
+        let recent_output = &output[output.len().saturating_sub(RECENT_OUTPUT_LEN)..];
+
+        format!(
+            "ip={} relative_base={} halt_reason={:?}\ninput={:?}\noutput={:?} ({} total)\nbreakpoints={:?} watchpoints={:?}",
+            self.program.ip,
+            self.program.relative_base,
+            self.program.halt_reason,
+            self.program.input,
+            recent_output,
+            output.len(),
+            self.breakpoints,
+            self.watchpoints,
+        )
+    }
+
+    // Read commands line by line until `quit` or the end of input. Type `help` for the commands.
+    pub fn repl<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        writeln!(output, "{}", self.current_instruction())?;
+
+        for line in input.lines() {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let numbers: Result<Vec<isize>, _> = words.iter().skip(1).map(|w| w.parse()).collect();
+            let numbers = match numbers {
+                Ok(numbers) => numbers,
+                Err(_) => {
+                    writeln!(output, "Arguments must be numbers: {}", line)?;
+                    continue;
+                }
+            };
+            // Everything but the values to poke or input is an address or a count
+            let unsigned_len = match words.first().copied() {
+                Some("in") | Some("input") => 0,
+                Some("poke") => 1,
+                _ => numbers.len(),
+            };
+            let unsigned: Option<Vec<usize>> = numbers
+                .iter()
+                .take(unsigned_len)
+                .map(|&n| usize::try_from(n).ok())
+                .collect();
+            let unsigned = match unsigned {
+                Some(unsigned) => unsigned,
+                None => {
+                    writeln!(output, "Arguments must be non-negative: {}", line)?;
+                    continue;
+                }
+            };
+            let address = unsigned.first().copied();
+
+            match (words.first().copied(), address) {
+                (None, _) => continue,
+                (Some("q"), _) | (Some("quit"), _) => break,
+                (Some("h"), _) | (Some("help"), _) => writeln!(output, "{}", HELP)?,
+                (Some("s"), _) | (Some("step"), _) => {
+                    let n = unsigned.first().copied().unwrap_or(1);
+                    for _ in 0..n {
+                        let stop = self.step();
+                        let stopped = stop != Ok(Stop::Halt(StepLimitReached));
+                        self.print_stop(&mut output, stop)?;
+                        if stopped {
+                            break;
+                        }
+                    }
+                }
+                (Some("r"), _) | (Some("back"), _) => {
+                    let n = unsigned.first().copied().unwrap_or(1);
+                    let undone = self.program.step_back(n);
+                    let history_left = self.program.history.as_ref().map_or(0, History::len);
+                    if undone < n && history_left == 0 {
+                        writeln!(output, "Stepped back {}; no earlier history", undone)?;
+                    } else if undone < n {
+                        writeln!(
+                            output,
+                            "Stepped back {}; the input or output can't be taken back",
+                            undone
+                        )?;
+                    }
+                    writeln!(output, "{}", self.current_instruction())?;
+                }
+                (Some("c"), _) | (Some("continue"), _) => {
+                    let stop = self.resume();
+                    self.print_stop(&mut output, stop)?;
+                }
+                (Some("b"), Some(address)) | (Some("break"), Some(address)) => {
+                    self.breakpoints.insert(address);
+                }
+                (Some("db"), Some(address)) | (Some("delete"), Some(address)) => {
+                    self.breakpoints.remove(&address);
+                }
+                (Some("w"), Some(address)) | (Some("watch"), Some(address)) => {
+                    self.watchpoints.insert(address);
+                }
+                (Some("dw"), Some(address)) | (Some("unwatch"), Some(address)) => {
+                    self.watchpoints.remove(&address);
+                }
+                (Some("x"), Some(address)) | (Some("mem"), Some(address)) => {
+                    let len = unsigned.get(1).copied().unwrap_or(1).min(MAX_MEM_LEN);
+                    let values: Vec<isize> = (address..address.saturating_add(len))
+                        .map(|idx| self.program.read_mem(idx))
+                        .collect();
+                    writeln!(output, "{}: {:?}", address, values)?;
+                }
+                (Some("poke"), Some(address)) if numbers.len() == 2 => {
+                    self.program.write_mem(address, numbers[1]);
+                }
+                (Some("in"), _) | (Some("input"), _) => {
+                    self.program.input.extend(numbers);
+                }
+                (Some("i"), _) | (Some("info"), _) => writeln!(output, "{}", self.state())?,
+                (Some("l"), _) | (Some("list"), _) => {
+                    writeln!(output, "{}", self.current_instruction())?
+                }
+                _ => writeln!(output, "Unknown command: {}", line)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn print_stop<W: Write>(
+        &self,
+        output: &mut W,
+        stop: Result<Stop, IntcodeError>,
+    ) -> io::Result<()> {
+        match stop {
+            Ok(Stop::Breakpoint(ip)) => writeln!(output, "Breakpoint at {}", ip)?,
+            Ok(Stop::Watchpoint { address, old, new }) => {
+                writeln!(output, "Watchpoint {}: {} -> {}", address, old, new)?
+            }
+            Ok(Stop::Halt(StepLimitReached)) => {}
+            Ok(Stop::Halt(halt_reason)) => writeln!(output, "{:?}", halt_reason)?,
+            Err(err) => writeln!(output, "Fault: {}", err)?,
+        }
+
+        writeln!(output, "{}", self.current_instruction())
+    }
+}
+
+const HELP: &str = "\
+s, step [n]           execute n instructions (default 1)
+r, back [n]           undo the last n instructions (default 1)
+c, continue           run to the next breakpoint, watchpoint, or halt
+b, break <ip>         add a breakpoint
+db, delete <ip>       remove a breakpoint
+w, watch <addr>       stop whenever the value at addr changes
+dw, unwatch <addr>    remove a watchpoint
+x, mem <addr> [n]     show n values starting at addr (default 1, at most 1000)
+poke <addr> <value>   write value to addr
+in, input <v>...      queue input values
+i, info               show ip, relative_base, input, recent output, and stops
+l, list               show the current instruction
+q, quit               leave the debugger";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use std::io::Cursor;
+
+    // Sum the inputs until a 0 is read, keeping the total in scratch space past the end of memory
+    fn program() -> Program {
+        let memory = assemble(
+            "
+                    ARB #100
+            loop:   IN @0
+                    JF @0, #done
+                    ADD @0, @1, @1
+                    JT #1, #loop
+            done:   OUT @1
+                    HLT
+            ",
+        )
+        .unwrap();
+        Program::new(memory, None)
+    }
+
+    #[test]
+    fn test_debugger() {
+        let mut debugger = Debugger::new(program());
+
+        assert_eq!(debugger.step(), Ok(Stop::Halt(StepLimitReached)));
+        assert_eq!(debugger.program.relative_base, 100);
+        assert_eq!(debugger.resume(), Ok(Stop::Halt(NeedsInput)));
+
+        debugger.program.input = vec![5, 6, 0].into();
+        debugger.breakpoints.insert(4);
+        assert_eq!(debugger.resume(), Ok(Stop::Breakpoint(4)));
+        assert_eq!(debugger.program.read_mem(100), 5);
+
+        debugger.breakpoints.clear();
+        debugger.watchpoints.insert(101);
+        assert_eq!(
+            debugger.resume(),
+            Ok(Stop::Watchpoint {
+                address: 101,
+                old: 0,
+                new: 5
+            }),
+        );
+        assert_eq!(debugger.program.ip, 11);
+        assert_eq!(
+            debugger.resume(),
+            Ok(Stop::Watchpoint {
+                address: 101,
+                old: 5,
+                new: 11
+            }),
+        );
+        assert_eq!(debugger.resume(), Ok(Stop::Halt(Halted)));
+        assert_eq!(debugger.program.output, vec![11]);
+    }
+
+    #[test]
+    fn test_repl() {
+        let mut debugger = Debugger::new(program());
+        let commands = "
+            b 11
+            c
+            in 7 0
+            c
+            info
+            poke 101 30
+            x 100 2
+            s 3
+            c
+            back 3
+            c
+            q
+            c
+        ";
+        let mut output = vec![];
+        debugger.repl(Cursor::new(commands), &mut output).unwrap();
+
+        assert_eq!(debugger.program.output, vec![30]);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            [
+                "    0  ARB #100",
+                "NeedsInput",
+                "    2  IN @0",
+                "Breakpoint at 11",
+                "   11  JT #1, #2            ; -> 2",
+                "ip=11 relative_base=100 halt_reason=Some(StepLimitReached)",
+                "input=[0]",
+                "output=[] (0 total)",
+                "breakpoints={11} watchpoints={}",
+                "100: [7, 30]",
+                "    2  IN @0",
+                "    4  JF @0, #14           ; -> 14",
+                "   14  OUT @1",
+                "Halted",
+                "   16  HLT",
+                "    4  JF @0, #14           ; -> 14",
+                "Halted",
+                "   16  HLT",
+                "",
+            ]
+            .join("\n"),
+        );
+    }
+
+    #[test]
+    fn test_repl_arguments() {
+        let mut debugger = Debugger::new(program());
+        let commands = [
+            "x -1 2",
+            "x 0 -1",
+            "back -1",
+            "poke -1 5",
+            "s x",
+            "x 9223372036854775806 2",
+            "x 0 100000",
+            "in -3",
+            "poke 0 -5",
+            "x 0",
+        ]
+        .join("\n");
+        let mut output = vec![];
+        debugger.repl(Cursor::new(commands), &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(
+            lines[..7],
+            [
+                "    0  ARB #100",
+                "Arguments must be non-negative: x -1 2",
+                "Arguments must be non-negative: x 0 -1",
+                "Arguments must be non-negative: back -1",
+                "Arguments must be non-negative: poke -1 5",
+                "Arguments must be numbers: s x",
+                "9223372036854775806: [0, 0]",
+            ]
+        );
+        // Long dumps are cut short
+        assert_eq!(lines[7].matches(',').count(), MAX_MEM_LEN - 1);
+        assert_eq!(lines[8..], ["0: [-5]"]);
+        assert_eq!(debugger.program.input, vec![-3]);
+        assert_eq!(debugger.program.ip, 0);
+    }
+}
@@ -0,0 +1,342 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::mpsc::{Receiver, Sender};
+
+// Where IN instructions get their values. Returning None pauses the program with NeedsInput, and
+// the same IN instruction asks again the next time the program is run.
+pub trait InputSource<W = isize> {
+    fn read(&mut self) -> Option<W>;
+
+    // Hand back a value that was read, so it's read again next, returning whether it could be.
+    // Used when stepping backwards through a program, which stops at a value that can't be.
+    fn unread(&mut self, value: W) -> bool;
+}
+
+// Where OUT instructions send their values
+pub trait OutputSink<W = isize> {
+    fn write(&mut self, value: W);
+
+    // Take back the most recently written value, returning whether it could be. Used when stepping
+    // backwards through a program, which stops at a value that can't be.
+    fn unwrite(&mut self) -> bool;
+}
+
+// The default input device: values queued up ahead of time, read first in first out
+impl<W> InputSource<W> for VecDeque<W> {
+    fn read(&mut self) -> Option<W> {
+        self.pop_front()
+    }
+
+    fn unread(&mut self, value: W) -> bool {
+        self.push_front(value);
+        true
+    }
+}
+
+impl<W> OutputSink<W> for VecDeque<W> {
+    fn write(&mut self, value: W) {
+        self.push_back(value);
+    }
+
+    fn unwrite(&mut self) -> bool {
+        self.pop_back().is_some()
+    }
+}
+
+// The default output device: every value ever written, in order
+impl<W> OutputSink<W> for Vec<W> {
+    fn write(&mut self, value: W) {
+        self.push(value);
+    }
+
+    fn unwrite(&mut self) -> bool {
+        self.pop().is_some()
+    }
+}
+
+// Borrow a device, so the caller can keep using it once the program is done with it
+impl<W, T: InputSource<W> + ?Sized> InputSource<W> for &mut T {
+    fn read(&mut self) -> Option<W> {
+        (**self).read()
+    }
+
+    fn unread(&mut self, value: W) -> bool {
+        (**self).unread(value)
+    }
+}
+
+impl<W, T: OutputSink<W> + ?Sized> OutputSink<W> for &mut T {
+    fn write(&mut self, value: W) {
+        (**self).write(value)
+    }
+
+    fn unwrite(&mut self) -> bool {
+        (**self).unwrite()
+    }
+}
+
+// Share a device, e.g. one that's both the program's input and its output
+impl<W, T: InputSource<W> + ?Sized> InputSource<W> for Rc<RefCell<T>> {
+    fn read(&mut self) -> Option<W> {
+        self.borrow_mut().read()
+    }
+
+    fn unread(&mut self, value: W) -> bool {
+        self.borrow_mut().unread(value)
+    }
+}
+
+impl<W, T: OutputSink<W> + ?Sized> OutputSink<W> for Rc<RefCell<T>> {
+    fn write(&mut self, value: W) {
+        self.borrow_mut().write(value)
+    }
+
+    fn unwrite(&mut self) -> bool {
+        self.borrow_mut().unwrite()
+    }
+}
+
+// Blocks until a value is sent; once every Sender is gone, the program pauses with NeedsInput.
+// Values received can't be put back.
+impl<W> InputSource<W> for Receiver<W> {
+    fn read(&mut self) -> Option<W> {
+        self.recv().ok()
+    }
+
+    fn unread(&mut self, _value: W) -> bool {
+        false
+    }
+}
+
+// Values written after the Receiver is gone are dropped. Values sent can't be taken back.
+impl<W> OutputSink<W> for Sender<W> {
+    fn write(&mut self, value: W) {
+        self.send(value).ok();
+    }
+
+    fn unwrite(&mut self) -> bool {
+        false
+    }
+}
+
+// Wrap a closure that supplies input values. What it's supplied can't be handed back.
+pub struct InputFn<F>(pub F);
+
+impl<W, F: FnMut() -> Option<W>> InputSource<W> for InputFn<F> {
+    fn read(&mut self) -> Option<W> {
+        (self.0)()
+    }
+
+    fn unread(&mut self, _value: W) -> bool {
+        false
+    }
+}
+
+// Wrap a closure that's called with each output value. What it's been called with can't be taken
+// back.
+pub struct OutputFn<F>(pub F);
+
+impl<W, F: FnMut(W)> OutputSink<W> for OutputFn<F> {
+    fn write(&mut self, value: W) {
+        (self.0)(value)
+    }
+
+    fn unwrite(&mut self) -> bool {
+        false
+    }
+}
+
+// Text input for programs that read ASCII codes, one character at a time
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct AsciiInput {
+    pub queue: VecDeque<isize>,
+}
+
+impl AsciiInput {
+    pub fn new() -> AsciiInput {
+        Default::default()
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        self.queue.extend(text.chars().map(|c| c as isize));
+    }
+
+    // Programs usually expect each command to end with a newline
+    pub fn push_line(&mut self, line: &str) {
+        self.push_str(line);
+        self.queue.push_back('\n' as isize);
+    }
+}
+
+impl InputSource for AsciiInput {
+    fn read(&mut self) -> Option<isize> {
+        self.queue.pop_front()
+    }
+
+    fn unread(&mut self, value: isize) -> bool {
+        self.queue.push_front(value);
+        true
+    }
+}
+
+// Text output for programs that write ASCII codes. Anything outside the ASCII range (like a final
+// answer) is kept separately in values. Values written can't be taken back, since which of the two
+// got the last one isn't kept.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct AsciiOutput {
+    pub text: String,
+    pub values: Vec<isize>,
+}
+
+impl AsciiOutput {
+    pub fn new() -> AsciiOutput {
+        Default::default()
+    }
+}
+
+impl OutputSink for AsciiOutput {
+    fn write(&mut self, value: isize) {
+        if (0..128).contains(&value) {
+            self.text.push(value as u8 as char);
+        } else {
+            self.values.push(value);
+        }
+    }
+
+    fn unwrite(&mut self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::program::{HaltReason::*, Program};
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    // Double every input value until a 0 is read
+    const DOUBLER: &str = "
+        loop:   IN x
+                JF x, #done
+                MUL x, #2, x
+                OUT x
+                JT #1, #loop
+        done:   HLT
+        x:      .data 0
+    ";
+
+    #[test]
+    fn test_devices() {
+        let memory = assemble(DOUBLER).unwrap();
+
+        // The default queue, which pauses when it runs dry
+        let mut program = Program::new(memory.clone(), Some(vec![1, 2]));
+        assert_eq!(program.run(), Ok(NeedsInput));
+        program.input.push_back(3);
+        program.input.push_back(0);
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.output, vec![2, 4, 6]);
+
+        // Closures
+        let mut inputs = vec![0, 5, 4];
+        let mut total = 0;
+        let mut program = Program::with_devices(
+            memory.clone(),
+            InputFn(|| inputs.pop()),
+            OutputFn(|value| total += value),
+        );
+        assert_eq!(program.run(), Ok(Halted));
+        drop(program);
+        assert_eq!(total, 18);
+
+        // Borrowed devices, and an output queue instead of a Vec
+        let mut input = VecDeque::from(vec![7, 0]);
+        let mut output = VecDeque::new();
+        assert_eq!(
+            Program::with_devices(memory.clone(), &mut input, &mut output).run(),
+            Ok(Halted),
+        );
+        assert_eq!((input.len(), output.pop_front()), (0, Some(14)));
+
+        // Channels, with the program on another thread
+        let (input_tx, input_rx) = channel();
+        let (output_tx, output_rx) = channel();
+        let handle =
+            thread::spawn(move || Program::with_devices(memory, input_rx, output_tx).run());
+        input_tx.send(10).unwrap();
+        assert_eq!(output_rx.recv(), Ok(20));
+        input_tx.send(21).unwrap();
+        assert_eq!(output_rx.recv(), Ok(42));
+        drop(input_tx);
+        assert_eq!(handle.join().unwrap(), Ok(NeedsInput));
+    }
+
+    #[test]
+    fn test_shared_device() {
+        // Reads back whatever was written last, starting from 1, and remembers everything written
+        struct Echo(Vec<isize>);
+
+        impl InputSource for Echo {
+            fn read(&mut self) -> Option<isize> {
+                Some(*self.0.last().unwrap_or(&1))
+            }
+
+            fn unread(&mut self, _value: isize) -> bool {
+                false
+            }
+        }
+
+        impl OutputSink for Echo {
+            fn write(&mut self, value: isize) {
+                self.0.push(value);
+            }
+
+            fn unwrite(&mut self) -> bool {
+                self.0.pop().is_some()
+            }
+        }
+
+        let memory = assemble(DOUBLER).unwrap();
+        let echo = Rc::new(RefCell::new(Echo(vec![])));
+        let mut program = Program::with_devices(memory, echo.clone(), echo.clone());
+        assert_eq!(program.run_until_output(), Ok(OutputReady));
+        assert_eq!(program.run_until_output(), Ok(OutputReady));
+        assert_eq!(program.run_until_output(), Ok(OutputReady));
+        assert_eq!(echo.borrow().0, vec![2, 4, 8]);
+    }
+
+    #[test]
+    fn test_ascii_devices() {
+        // Upper-case each character until a '.', then write 1000
+        let memory = assemble(
+            "
+            loop:   IN c
+                    EQ c, #46, done
+                    JT done, #end
+                    LT c, #97, lower
+                    JT lower, #echo
+                    ADD c, #-32, c
+            echo:   OUT c
+                    JT #1, #loop
+            end:    OUT #1000
+                    HLT
+            c:      .data 0
+            done:   .data 0
+            lower:  .data 0
+            ",
+        )
+        .unwrap();
+
+        let mut input = AsciiInput::new();
+        input.push_line("Hello");
+        input.push_str("world.");
+        let mut program = Program::with_devices(memory, input, AsciiOutput::new());
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.output.text, "HELLO\nWORLD");
+        assert_eq!(program.output.values, vec![1000]);
+        assert!(program.input.queue.is_empty());
+    }
+}
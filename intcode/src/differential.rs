@@ -0,0 +1,526 @@
+// Differential fuzzing: random, well-formed programs are run on every way the VM has of running
+// one, and anything they disagree on (halt reason or fault, ip, relative base, steps, memory, or
+// output) fails the test. proptest then shrinks the program and its input down to a minimal
+// reproducer, which is shown disassembled.
+//
+// The engines include running on i64, i128 and BigInt words as well as isize. The translated code
+// isn't among them: it only exists once rustc has compiled it, which can't happen for each case,
+// and its run() takes no step limit, so the many random programs that loop forever would never
+// come back. translate's own tests and intcode's tests/translated.rs check it against the
+// interpreter instead, on every day's program, patched and unpatched.
+//
+// More cases can be run with e.g. PROPTEST_CASES=10000 cargo test differential.
+
+#[cfg(feature = "bigint")]
+use num_bigint::BigInt;
+use proptest::prelude::*;
+use proptest::strategy::{NewTree, ValueTree};
+use proptest::test_runner::TestRunner;
+
+use crate::block_cache::BlockCache;
+use crate::disassembler::listing;
+use crate::error::IntcodeError;
+use crate::history::History;
+use crate::hooks::{HookAction, Hooks};
+use crate::memory::PagedMemory;
+use crate::operation::Operation;
+use crate::program::{
+    HaltReason, Input, Memory, ParameterUse, Program, MAX_PARAMS, PARAM_MODE_PLACES,
+};
+use crate::word::Word;
+use HaltReason::*;
+
+// Random programs loop as often as not, so every run is cut off here
+const MAX_STEPS: usize = 2000;
+
+// Cells past the end of memory that position mode operands can point at
+const SCRATCH_LEN: isize = 16;
+
+// In the order instructions shrink towards: OUT first, since output is what shows most of what a
+// program did, and then HLT
+const OPCODES: [isize; 10] = [4, 99, 1, 2, 3, 5, 6, 7, 8, 9];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Engine {
+    Interpreter,
+    BlockCache,
+    // Hooks on every address, that let everything through
+    Hooked,
+    HookedBlockCache,
+    // One instruction at a time with step()
+    Stepped,
+    // Run with a history, step back over half of what ran, then run again
+    Rewound,
+    // Other words, on the interpreter and the block cache. Words wider than isize don't overflow
+    // where isize does, so they're only compared on programs that don't overflow.
+    I64,
+    I128,
+    I128BlockCache,
+    #[cfg(feature = "bigint")]
+    BigInt,
+    #[cfg(feature = "bigint")]
+    BigIntBlockCache,
+}
+
+fn engines() -> Vec<Engine> {
+    let engines = vec![
+        Engine::Interpreter,
+        Engine::BlockCache,
+        Engine::Hooked,
+        Engine::HookedBlockCache,
+        Engine::Stepped,
+        Engine::Rewound,
+        Engine::I64,
+        Engine::I128,
+        Engine::I128BlockCache,
+    ];
+    #[cfg(feature = "bigint")]
+    let engines = [engines, vec![Engine::BigInt, Engine::BigIntBlockCache]].concat();
+    engines
+}
+
+#[derive(Debug, Eq, PartialEq)]
+struct Outcome<W: Word = isize> {
+    result: Result<HaltReason, IntcodeError>,
+    ip: usize,
+    relative_base: usize,
+    steps: usize,
+    memory: PagedMemory<W>,
+    output: Vec<W>,
+}
+
+// An opcode, and a mode and value for each of its params. Which of them are used, and what the
+// values mean, is worked out by layout() once it knows where everything is.
+#[derive(Debug, Clone)]
+struct Instruction {
+    opcode: isize,
+    operands: [(isize, isize); MAX_PARAMS],
+}
+
+fn instruction() -> impl Strategy<Value = Instruction> {
+    (
+        prop::sample::select(&OPCODES[..]),
+        prop::array::uniform3((0isize..3, -8isize..24)),
+    )
+        .prop_map(|(opcode, operands)| Instruction { opcode, operands })
+}
+
+// The instructions one after another, followed by the data. Position mode operands point anywhere
+// in memory or just past it, immediate jump targets are always the start of an instruction, and
+// writes are never in immediate mode, so every program decodes; what it does when run, self-
+// modifying or faulting included, is up to chance.
+fn layout(instructions: &[Instruction], data: &[isize]) -> Memory {
+    let operation = |instruction: &Instruction| Operation::from_opcode(instruction.opcode).unwrap();
+    let mut starts = vec![];
+    let mut len = 0;
+    for instruction in instructions {
+        starts.push(len as isize);
+        len += 1 + operation(instruction).params_at.len();
+    }
+    let memory_len = (len + data.len()) as isize;
+
+    let mut memory = vec![];
+    for instruction in instructions {
+        let operation = operation(instruction);
+        let idx = memory.len();
+        memory.push(instruction.opcode);
+
+        for pat in operation.params_at {
+            let (mut mode, value) = instruction.operands[pat.0];
+            if pat.1 == ParameterUse::Write && mode == 1 {
+                mode = 0;
+            }
+            memory[idx] += mode * PARAM_MODE_PLACES[pat.0];
+
+            let is_jump_target = (operation.opcode == 5 || operation.opcode == 6) && pat.0 == 1;
+            memory.push(match mode {
+                0 => value.rem_euclid(memory_len + SCRATCH_LEN),
+                1 if is_jump_target => starts[value.rem_euclid(starts.len() as isize) as usize],
+                _ => value,
+            });
+        }
+    }
+    memory.extend(data);
+
+    memory
+}
+
+#[derive(Debug, Clone)]
+struct Generated {
+    instructions: Vec<Instruction>,
+    data: Vec<isize>,
+    input: Input,
+}
+
+// Random programs, as their memory and input, which shrink with Shrinker
+#[derive(Debug)]
+struct Programs;
+
+fn program() -> Programs {
+    Programs
+}
+
+impl Strategy for Programs {
+    type Tree = Shrinker;
+    type Value = (Memory, Input);
+
+    fn new_tree(&self, runner: &mut TestRunner) -> NewTree<Self> {
+        let (instructions, data, input) = (
+            prop::collection::vec(instruction(), 1..24),
+            prop::collection::vec(-5isize..20, 0..8),
+            prop::collection::vec(-3isize..10, 0..8),
+        )
+            .new_tree(runner)?
+            .current();
+        let generated = Generated {
+            instructions,
+            data,
+            input,
+        };
+
+        Ok(Shrinker {
+            accepted: generated.clone(),
+            current: generated,
+            candidates: vec![],
+        })
+    }
+}
+
+// Shrinks a failing program by trying everything one step simpler than it, in turn: without each
+// instruction, data value or input value, and with each opcode, mode, and value moved towards the
+// first opcode or 0. The first of these that still fails is taken, and everything one step simpler
+// than that is tried next, until nothing is.
+//
+// proptest's own collection and tuple shrinking go over each part only once, so they can get stuck
+// on an instruction that only became deletable once something after it was simplified. This can't,
+// so the same failure always shrinks to the same program, whatever the seed. In particular, any
+// program that outputs something shrinks to a lone OUT [0], since that's what its first
+// instruction can always become without losing the output, after which everything else can go.
+struct Shrinker {
+    // The simplest program that's failed so far
+    accepted: Generated,
+    current: Generated,
+    // What's left to try, last first
+    candidates: Vec<Generated>,
+}
+
+impl Shrinker {
+    fn try_next(&mut self) -> bool {
+        match self.candidates.pop() {
+            Some(candidate) => {
+                self.current = candidate;
+                true
+            }
+            None => {
+                self.current = self.accepted.clone();
+                false
+            }
+        }
+    }
+}
+
+impl ValueTree for Shrinker {
+    type Value = (Memory, Input);
+
+    fn current(&self) -> (Memory, Input) {
+        (
+            layout(&self.current.instructions, &self.current.data),
+            self.current.input.clone(),
+        )
+    }
+
+    // Only called when the current program has failed
+    fn simplify(&mut self) -> bool {
+        self.accepted = self.current.clone();
+        self.candidates = simpler(&self.accepted);
+        self.candidates.reverse();
+        self.try_next()
+    }
+
+    fn complicate(&mut self) -> bool {
+        self.try_next()
+    }
+}
+
+// Everything one step simpler than a program, roughly simplest first
+fn simpler(generated: &Generated) -> Vec<Generated> {
+    let mut candidates = vec![];
+    let mut with = |change: &dyn Fn(&mut Generated)| {
+        let mut candidate = generated.clone();
+        change(&mut candidate);
+        candidates.push(candidate);
+    };
+
+    if generated.instructions.len() > 1 {
+        for idx in 0..generated.instructions.len() {
+            with(&|g| {
+                g.instructions.remove(idx);
+            });
+        }
+    }
+    for idx in 0..generated.data.len() {
+        with(&|g| {
+            g.data.remove(idx);
+        });
+    }
+    for idx in 0..generated.input.len() {
+        with(&|g| {
+            g.input.remove(idx);
+        });
+    }
+
+    for (idx, instruction) in generated.instructions.iter().enumerate() {
+        let opcode_idx = OPCODES
+            .iter()
+            .position(|&o| o == instruction.opcode)
+            .unwrap();
+        for simpler_idx in towards_zero(opcode_idx as isize) {
+            with(&|g| g.instructions[idx].opcode = OPCODES[simpler_idx as usize]);
+        }
+        for (param, &(mode, value)) in instruction.operands.iter().enumerate() {
+            for mode in towards_zero(mode) {
+                with(&|g| g.instructions[idx].operands[param].0 = mode);
+            }
+            for value in towards_zero(value) {
+                with(&|g| g.instructions[idx].operands[param].1 = value);
+            }
+        }
+    }
+    for (idx, &value) in generated.data.iter().enumerate() {
+        for value in towards_zero(value) {
+            with(&|g| g.data[idx] = value);
+        }
+    }
+    for (idx, &value) in generated.input.iter().enumerate() {
+        for value in towards_zero(value) {
+            with(&|g| g.input[idx] = value);
+        }
+    }
+
+    candidates
+}
+
+// 0, halfway there, and one step closer, for as many of those as are closer to 0 than value
+fn towards_zero(value: isize) -> Vec<isize> {
+    let mut values = vec![0, value / 2, value - value.signum()];
+    values.dedup();
+    values.retain(|&v| v != value);
+    values
+}
+
+fn let_everything_through() -> Hooks {
+    let mut hooks = Hooks::new();
+    hooks
+        .on_read(.., |_, _| HookAction::Allow)
+        .on_write(.., |_, _| HookAction::Allow);
+    hooks
+}
+
+fn run(engine: Engine, memory: &Memory, input: &Input) -> Outcome {
+    let mut program = Program::new(memory.clone(), Some(input.clone()));
+    if let Engine::BlockCache | Engine::HookedBlockCache = engine {
+        program.block_cache = Some(BlockCache::new());
+    }
+    if let Engine::Hooked | Engine::HookedBlockCache = engine {
+        program.hooks = Some(let_everything_through());
+    }
+
+    let result = match engine {
+        Engine::Stepped => {
+            let mut result = Ok(StepLimitReached);
+            for _ in 0..MAX_STEPS {
+                result = program.step();
+                if result != Ok(StepLimitReached) && result != Ok(OutputReady) {
+                    break;
+                }
+            }
+            match result {
+                Ok(OutputReady) => Ok(StepLimitReached),
+                result => result,
+            }
+        }
+        Engine::Rewound => {
+            program.history = Some(History::new(MAX_STEPS));
+            program.run_for(MAX_STEPS).ok();
+            program.step_back(program.steps / 2 + 1);
+            program.run_for(MAX_STEPS - program.steps)
+        }
+        _ => program.run_for(MAX_STEPS),
+    };
+
+    Outcome {
+        result,
+        ip: program.ip,
+        relative_base: program.relative_base,
+        steps: program.steps,
+        memory: program.memory,
+        output: program.output,
+    }
+}
+
+fn run_words<W: Word>(memory: &Memory, input: &Input, block_cache: bool) -> Outcome<W> {
+    let words = |values: &[isize]| values.iter().map(|&value| W::from_isize(value)).collect();
+    let mut program = Program::from_words(words(memory), Some(words(input)));
+    if block_cache {
+        program.block_cache = Some(BlockCache::new());
+    }
+    let result = program.run_for(MAX_STEPS);
+
+    Outcome {
+        result,
+        ip: program.ip,
+        relative_base: program.relative_base,
+        steps: program.steps,
+        memory: program.memory,
+        output: program.output,
+    }
+}
+
+// The outcome, on other words
+fn widen<W: Word>(outcome: &Outcome) -> Outcome<W> {
+    let mut memory: PagedMemory<W> = outcome
+        .memory
+        .to_vec()
+        .into_iter()
+        .map(W::from_isize)
+        .collect::<Vec<W>>()
+        .into();
+    for (address, value) in outcome.memory.nonzero_cells() {
+        if address >= memory.len() {
+            memory.write(address, W::from_isize(value));
+        }
+    }
+
+    Outcome {
+        result: outcome.result.clone(),
+        ip: outcome.ip,
+        relative_base: outcome.relative_base,
+        steps: outcome.steps,
+        memory,
+        output: outcome
+            .output
+            .iter()
+            .map(|&value| W::from_isize(value))
+            .collect(),
+    }
+}
+
+// The outcome, shown as a disagreement if it isn't the one expected
+fn disagreement<W: Word>(expected: &Outcome, outcome: Outcome<W>) -> Option<String> {
+    if outcome == widen(expected) {
+        None
+    } else {
+        Some(format!("{:#?}", outcome))
+    }
+}
+
+// Every engine's outcome that isn't the same as the plain interpreter's
+fn disagreements(memory: &Memory, input: &Input) -> Vec<(Engine, String)> {
+    let expected = run(Engine::Interpreter, memory, input);
+    let overflowed = matches!(expected.result, Err(IntcodeError::Overflow { .. }));
+
+    engines()[1..]
+        .iter()
+        .filter_map(|&engine| {
+            let disagreement = match engine {
+                Engine::I64 => disagreement(&expected, run_words::<i64>(memory, input, false)),
+                Engine::I128 | Engine::I128BlockCache if overflowed => None,
+                Engine::I128 => disagreement(&expected, run_words::<i128>(memory, input, false)),
+                Engine::I128BlockCache => {
+                    disagreement(&expected, run_words::<i128>(memory, input, true))
+                }
+                #[cfg(feature = "bigint")]
+                Engine::BigInt | Engine::BigIntBlockCache if overflowed => None,
+                #[cfg(feature = "bigint")]
+                Engine::BigInt => {
+                    disagreement(&expected, run_words::<BigInt>(memory, input, false))
+                }
+                #[cfg(feature = "bigint")]
+                Engine::BigIntBlockCache => {
+                    disagreement(&expected, run_words::<BigInt>(memory, input, true))
+                }
+                _ => disagreement(&expected, run(engine, memory, input)),
+            };
+            disagreement.map(|outcome| (engine, outcome))
+        })
+        .collect()
+}
+
+proptest! {
+    #[test]
+    fn test_engines_agree((memory, input) in program()) {
+        let disagreements = disagreements(&memory, &input);
+        prop_assert!(
+            disagreements.is_empty(),
+            "{:?} on input {:?}\n{}\nInterpreter: {:#?}\n{}",
+            disagreements.iter().map(|(engine, _)| engine).collect::<Vec<_>>(),
+            input,
+            listing(&memory),
+            run(Engine::Interpreter, &memory, &input),
+            disagreements
+                .iter()
+                .map(|(engine, outcome)| format!("{:?}: {}", engine, outcome))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+    }
+}
+
+#[test]
+fn test_layout() {
+    let instructions = [
+        // IN @3; JT [9], #(start of 2nd); OUT #-2; HLT
+        Instruction {
+            opcode: 3,
+            operands: [(2, 3), (0, 0), (0, 0)],
+        },
+        Instruction {
+            opcode: 5,
+            operands: [(0, 9 + 25), (1, 1), (1, 7)],
+        },
+        Instruction {
+            opcode: 4,
+            operands: [(1, -2), (2, 0), (2, 0)],
+        },
+        Instruction {
+            opcode: 99,
+            operands: [(1, 0), (1, 0), (1, 0)],
+        },
+    ];
+    assert_eq!(
+        layout(&instructions, &[7]),
+        vec![203, 3, 1005, 9, 2, 104, -2, 99, 7]
+    );
+
+    // Immediate mode writes are made position mode instead
+    let instructions = [Instruction {
+        opcode: 3,
+        operands: [(1, 2), (0, 0), (0, 0)],
+    }];
+    assert_eq!(layout(&instructions, &[]), vec![3, 2]);
+}
+
+#[test]
+fn test_shrinking() {
+    use proptest::test_runner::{Config, TestError};
+
+    // An engine that loses the last value output is caught, and shrunk down to a lone OUT [0],
+    // whatever the seed. Shrinking is left to run for as long as it takes to get there.
+    let config = Config {
+        failure_persistence: None,
+        max_shrink_iters: 1_000_000,
+        ..Config::default()
+    };
+    let mut runner = TestRunner::new(config);
+    let result = runner.run(&program(), |(memory, input)| {
+        let mut outcome = run(Engine::Interpreter, &memory, &input);
+        outcome.output.pop();
+        prop_assert_eq!(outcome, run(Engine::Interpreter, &memory, &input));
+        Ok(())
+    });
+
+    match result {
+        Err(TestError::Fail(_, program)) => assert_eq!(program, (vec![4, 0], vec![])),
+        result => panic!("Expected a failure, got {:?}", result),
+    }
+}
@@ -0,0 +1,351 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use crate::memory::PagedMemory;
+use crate::operation::Operation;
+use crate::program::{
+    Instruction, Memory, ParameterMode, ParameterUse, Program, PARAM_MODE_PLACES,
+};
+use ParameterMode::*;
+
+// Operands are written the same way the assembler reads them:
+//   4   position mode; the value at address 4
+//   #4  immediate mode; the value 4 itself
+//   @4  relative mode; the value at address relative_base + 4
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Operand<W = isize> {
+    pub mode: ParameterMode,
+    pub param_use: ParameterUse,
+    pub value: W,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Line {
+    Instruction {
+        address: usize,
+        instruction: Instruction,
+        operation: &'static Operation,
+        operands: Vec<Operand>,
+    },
+    Data {
+        address: usize,
+        value: isize,
+    },
+}
+
+impl Line {
+    pub fn address(&self) -> usize {
+        match self {
+            Line::Instruction { address, .. } | Line::Data { address, .. } => *address,
+        }
+    }
+
+    // The raw memory cells this line was decoded from
+    pub fn words(&self) -> Vec<isize> {
+        match self {
+            Line::Instruction {
+                instruction,
+                operands,
+                ..
+            } => {
+                let mut words = vec![*instruction];
+                words.extend(operands.iter().map(|operand| operand.value));
+                words
+            }
+            Line::Data { value, .. } => vec![*value],
+        }
+    }
+
+    // Where a jump continues when taken, if that's known without running the program. Jumps
+    // through position or relative mode operands are computed at runtime and resolve to None.
+    pub fn jump_target(&self) -> Option<usize> {
+        match self {
+            Line::Instruction {
+                operation,
+                operands,
+                ..
+            } if operation.is_jump() => match operands[1] {
+                Operand {
+                    mode: ImmediateMode,
+                    value,
+                    ..
+                } if value >= 0 => Some(value as usize),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub(crate) fn annotation(&self) -> Option<String> {
+        match self {
+            Line::Instruction { operation, .. } if operation.is_jump() => {
+                match self.jump_target() {
+                    Some(target) => Some(format!("-> {}", target)),
+                    None => Some("-> computed".to_string()),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<W: Display> Display for Operand<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            PositionMode => write!(f, "{}", self.value),
+            ImmediateMode => write!(f, "#{}", self.value),
+            RelativeMode => write!(f, "@{}", self.value),
+        }
+    }
+}
+
+impl Display for Line {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Line::Instruction {
+                operation,
+                operands,
+                ..
+            } => {
+                write!(f, "{}", operation.mnemonic)?;
+                for (idx, operand) in operands.iter().enumerate() {
+                    let separator = if idx == 0 { " " } else { ", " };
+                    write!(f, "{}{}", separator, operand)?;
+                }
+                Ok(())
+            }
+            Line::Data { value, .. } => write!(f, ".data {}", value),
+        }
+    }
+}
+
+// Linear sweep from address 0. Anything that run() would fault on, that runs off the end of memory,
+// or that has mode digits the assembler wouldn't produce is shown as data, one cell at a time.
+pub fn disassemble(memory: &Memory) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut address = 0;
+
+    while address < memory.len() {
+        let line = decode_at(memory, address);
+        address += line.words().len();
+        lines.push(line);
+    }
+
+    lines
+}
+
+// Decode the single instruction at address, or show it as data if it can't be decoded
+pub fn decode_at(memory: &Memory, address: usize) -> Line {
+    decode_cells(|address| memory[address], memory.len(), address)
+}
+
+// Like decode_at(), reading just the cells the instruction takes up out of a program's memory
+pub(crate) fn decode_in(memory: &PagedMemory, address: usize) -> Line {
+    decode_cells(|address| memory.read(address), memory.len(), address)
+}
+
+fn decode_cells<F: Fn(usize) -> isize>(cell: F, len: usize, address: usize) -> Line {
+    decode(&cell, len, address).unwrap_or(Line::Data {
+        address,
+        value: cell(address),
+    })
+}
+
+fn decode<F: Fn(usize) -> isize>(cell: &F, len: usize, address: usize) -> Option<Line> {
+    let instruction = cell(address);
+    let operation = Operation::from_opcode(Program::parse_opcode(instruction))?;
+    let n_params = operation.params_at.len();
+
+    if address + operation.size() > len {
+        return None;
+    }
+    if instruction / PARAM_MODE_PLACES[n_params] != 0 {
+        return None;
+    }
+
+    let param_modes = Program::parse_param_modes(instruction, n_params).ok()?;
+    let mut operands = Vec::with_capacity(n_params);
+
+    for pat in operation.params_at {
+        let operand = Operand {
+            mode: param_modes[pat.0],
+            param_use: pat.1,
+            value: cell(address + 1 + pat.0),
+        };
+        if operand.param_use == ParameterUse::Write && operand.mode == ImmediateMode {
+            return None;
+        }
+        operands.push(operand);
+    }
+
+    Some(Line::Instruction {
+        address,
+        instruction,
+        operation,
+        operands,
+    })
+}
+
+// Just the decoded instructions and data, in a form the assembler reads back
+pub fn source(memory: &Memory) -> String {
+    disassemble(memory)
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+// One line per instruction or data cell: address, raw memory cells, then the decoded instruction
+pub fn listing(memory: &Memory) -> String {
+    let lines = disassemble(memory);
+    let words: Vec<String> = lines
+        .iter()
+        .map(|line| {
+            let words: Vec<String> = line.words().iter().map(|w| w.to_string()).collect();
+            words.join(" ")
+        })
+        .collect();
+    let address_width = memory.len().saturating_sub(1).to_string().len();
+    let words_width = words.iter().map(|w| w.len()).max().unwrap_or(0);
+
+    let mut listing = String::new();
+    for (line, words) in lines.iter().zip(words) {
+        let text = line.to_string();
+        let text = match line.annotation() {
+            Some(annotation) => format!("{:<20} ; {}", text, annotation),
+            None => text,
+        };
+        listing.push_str(&format!(
+            "{:>address_width$}  {:<words_width$}  {}\n",
+            line.address(),
+            words,
+            text,
+            address_width = address_width,
+            words_width = words_width,
+        ));
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operation::{ADD, JT};
+    use ParameterUse::*;
+
+    #[test]
+    fn test_disassemble() {
+        let lines = disassemble(&vec![1001, 4, -3, 4, 1105, 1, 0, 99, 0, 10099]);
+
+        assert_eq!(
+            lines[0],
+            Line::Instruction {
+                address: 0,
+                instruction: 1001,
+                operation: &ADD,
+                operands: vec![
+                    Operand {
+                        mode: PositionMode,
+                        param_use: Read,
+                        value: 4
+                    },
+                    Operand {
+                        mode: ImmediateMode,
+                        param_use: Read,
+                        value: -3
+                    },
+                    Operand {
+                        mode: PositionMode,
+                        param_use: Write,
+                        value: 4
+                    },
+                ],
+            },
+        );
+        assert_eq!(lines[1].address(), 4);
+        assert_eq!(
+            lines[1],
+            Line::Instruction {
+                address: 4,
+                instruction: 1105,
+                operation: &JT,
+                operands: vec![
+                    Operand {
+                        mode: ImmediateMode,
+                        param_use: Read,
+                        value: 1
+                    },
+                    Operand {
+                        mode: ImmediateMode,
+                        param_use: Read,
+                        value: 0
+                    },
+                ],
+            },
+        );
+        assert_eq!(lines[1].jump_target(), Some(0));
+        assert_eq!(
+            lines[3..],
+            [
+                Line::Data {
+                    address: 8,
+                    value: 0
+                },
+                Line::Data {
+                    address: 9,
+                    value: 10099
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn test_disassemble_data() {
+        let as_text = |memory: Memory| -> Vec<String> {
+            disassemble(&memory)
+                .iter()
+                .map(|line| line.to_string())
+                .collect()
+        };
+
+        // Immediate mode writes, unknown parameter modes, and truncated instructions
+        assert_eq!(
+            as_text(vec![11101, 301, 1, 2, 3]),
+            vec![".data 11101", ".data 301", ".data 1", ".data 2", ".data 3"],
+        );
+        assert_eq!(
+            as_text(vec![1, 1, 2]),
+            vec![".data 1", ".data 1", ".data 2"],
+        );
+        assert_eq!(
+            as_text(vec![203, 5, 21108, 1, -1, 7, 109, -4, 99]),
+            vec!["IN @5", "EQ #1, #-1, @7", "ARB #-4", "HLT"],
+        );
+    }
+
+    #[test]
+    fn test_listing() {
+        assert_eq!(
+            listing(&vec![3, 11, 1006, 11, 10, 104, 1, 1105, 1, 0, 99, 0]),
+            [
+                " 0  3 11        IN 11",
+                " 2  1006 11 10  JF 11, #10           ; -> 10",
+                " 5  104 1       OUT #1",
+                " 7  1105 1 0    JT #1, #0            ; -> 0",
+                "10  99          HLT",
+                "11  0           .data 0",
+                "",
+            ]
+            .join("\n"),
+        );
+        assert_eq!(
+            listing(&vec![5, 0, 1, 2106, 0, 3]),
+            [
+                "0  5 0 1     JT 0, 1              ; -> computed",
+                "3  2106 0 3  JF #0, @3            ; -> computed",
+                "",
+            ]
+            .join("\n"),
+        );
+    }
+}
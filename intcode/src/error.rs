@@ -0,0 +1,290 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+use crate::program::Instruction;
+use crate::recording::RECORDING_VERSION;
+use crate::snapshot::SNAPSHOT_VERSION;
+
+// Every variant carries the ip of the faulting instruction along with the instruction itself; the
+// Program is left untouched at that ip so it can be inspected after the fact.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum IntcodeError {
+    UnknownOpcode {
+        ip: usize,
+        instruction: Instruction,
+    },
+    UnknownParameterMode {
+        ip: usize,
+        instruction: Instruction,
+        mode: isize,
+    },
+    ImmediateModeWrite {
+        ip: usize,
+        instruction: Instruction,
+    },
+    NegativeAddress {
+        ip: usize,
+        instruction: Instruction,
+        address: isize,
+    },
+    // An ADD or MUL result too big for the word type, or an address or relative base adjustment
+    // too big for an isize
+    Overflow {
+        ip: usize,
+        instruction: Instruction,
+    },
+}
+
+impl IntcodeError {
+    pub fn ip(&self) -> usize {
+        use IntcodeError::*;
+
+        match self {
+            UnknownOpcode { ip, .. }
+            | UnknownParameterMode { ip, .. }
+            | ImmediateModeWrite { ip, .. }
+            | NegativeAddress { ip, .. }
+            | Overflow { ip, .. } => *ip,
+        }
+    }
+
+    pub fn instruction(&self) -> Instruction {
+        use IntcodeError::*;
+
+        match self {
+            UnknownOpcode { instruction, .. }
+            | UnknownParameterMode { instruction, .. }
+            | ImmediateModeWrite { instruction, .. }
+            | NegativeAddress { instruction, .. }
+            | Overflow { instruction, .. } => *instruction,
+        }
+    }
+}
+
+impl Display for IntcodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use IntcodeError::*;
+
+        match self {
+            UnknownOpcode { .. } => write!(f, "Unknown opcode")?,
+            UnknownParameterMode { mode, .. } => write!(f, "Unknown parameter mode: {}", mode)?,
+            ImmediateModeWrite { .. } => write!(f, "ImmediateMode cannot be used for writes")?,
+            NegativeAddress { address, .. } => write!(f, "Negative address: {}", address)?,
+            Overflow { .. } => write!(f, "Overflow")?,
+        }
+
+        write!(f, " (ip={}, instruction={})", self.ip(), self.instruction())
+    }
+}
+
+impl Error for IntcodeError {}
+
+// Every variant carries the 1-based line of the assembler source it was found on
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AssembleError {
+    UnknownMnemonic {
+        line: usize,
+        mnemonic: String,
+    },
+    WrongOperandCount {
+        line: usize,
+        mnemonic: String,
+        expected: usize,
+        found: usize,
+    },
+    InvalidOperand {
+        line: usize,
+        operand: String,
+    },
+    ImmediateModeWrite {
+        line: usize,
+        operand: String,
+    },
+    InvalidLabel {
+        line: usize,
+        label: String,
+    },
+    UnknownLabel {
+        line: usize,
+        label: String,
+    },
+    DuplicateLabel {
+        line: usize,
+        label: String,
+    },
+}
+
+impl AssembleError {
+    pub fn line(&self) -> usize {
+        use AssembleError::*;
+
+        match self {
+            UnknownMnemonic { line, .. }
+            | WrongOperandCount { line, .. }
+            | InvalidOperand { line, .. }
+            | ImmediateModeWrite { line, .. }
+            | InvalidLabel { line, .. }
+            | UnknownLabel { line, .. }
+            | DuplicateLabel { line, .. } => *line,
+        }
+    }
+}
+
+impl Display for AssembleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use AssembleError::*;
+
+        match self {
+            UnknownMnemonic { mnemonic, .. } => write!(f, "Unknown mnemonic: {}", mnemonic)?,
+            WrongOperandCount {
+                mnemonic,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "{} takes {} operands, found {}",
+                mnemonic, expected, found
+            )?,
+            InvalidOperand { operand, .. } => write!(f, "Invalid operand: {}", operand)?,
+            ImmediateModeWrite { operand, .. } => {
+                write!(f, "ImmediateMode cannot be used for writes: {}", operand)?
+            }
+            InvalidLabel { label, .. } => write!(f, "Invalid label: {}", label)?,
+            UnknownLabel { label, .. } => write!(f, "Unknown label: {}", label)?,
+            DuplicateLabel { label, .. } => write!(f, "Duplicate label: {}", label)?,
+        }
+
+        write!(f, " (line {})", self.line())
+    }
+}
+
+impl Error for AssembleError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseMemoryError {
+    pub address: usize,
+    pub value: String,
+}
+
+impl Display for ParseMemoryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid value at address {}: {}",
+            self.address, self.value
+        )
+    }
+}
+
+impl Error for ParseMemoryError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SnapshotError {
+    InvalidHeader { header: String },
+    UnsupportedVersion { version: u32 },
+    UnknownField { field: String },
+    DuplicateField { field: String },
+    MissingField { field: &'static str },
+    InvalidValue { field: &'static str, value: String },
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use SnapshotError::*;
+
+        match self {
+            InvalidHeader { header } => write!(f, "Not an Intcode snapshot: {}", header),
+            UnsupportedVersion { version } => write!(
+                f,
+                "Unsupported snapshot version: {} (expected {})",
+                version, SNAPSHOT_VERSION
+            ),
+            UnknownField { field } => write!(f, "Unknown field: {}", field),
+            DuplicateField { field } => write!(f, "Duplicate field: {}", field),
+            MissingField { field } => write!(f, "Missing field: {}", field),
+            InvalidValue { field, value } => write!(f, "Invalid {} value: {}", field, value),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RecordingError {
+    InvalidHeader { header: String },
+    UnsupportedVersion { version: u32 },
+    // line is 1-based
+    InvalidLine { line: usize, text: String },
+}
+
+impl Display for RecordingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use RecordingError::*;
+
+        match self {
+            InvalidHeader { header } => write!(f, "Not an Intcode recording: {}", header),
+            UnsupportedVersion { version } => write!(
+                f,
+                "Unsupported recording version: {} (expected {})",
+                version, RECORDING_VERSION
+            ),
+            InvalidLine { line, text } => write!(f, "Invalid input on line {}: {}", line, text),
+        }
+    }
+}
+
+impl Error for RecordingError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ReplayError {
+    // The program didn't read the value recorded at this step there
+    Diverged { step: usize },
+    Fault(IntcodeError),
+}
+
+impl From<IntcodeError> for ReplayError {
+    fn from(err: IntcodeError) -> ReplayError {
+        ReplayError::Fault(err)
+    }
+}
+
+impl Display for ReplayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Diverged { step } => write!(
+                f,
+                "Replay diverged: the input recorded at step {} wasn't read there",
+                step
+            ),
+            ReplayError::Fault(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ThreadedError {
+    // Packets take more than one channel to a program, so can't be routed between threads
+    PacketRouting,
+    Fault(IntcodeError),
+}
+
+impl From<IntcodeError> for ThreadedError {
+    fn from(err: IntcodeError) -> ThreadedError {
+        ThreadedError::Fault(err)
+    }
+}
+
+impl Display for ThreadedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ThreadedError::PacketRouting => write!(f, "Packets can't be routed between threads"),
+            ThreadedError::Fault(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ThreadedError {}
@@ -0,0 +1,236 @@
+use std::collections::VecDeque;
+
+use crate::device::{InputSource, OutputSink};
+use crate::program::Program;
+use crate::word::Word;
+
+// Set Program::history to record enough about each instruction to undo it, so the program can be
+// stepped backwards. Only the most recent `capacity` instructions are kept; each takes a few words,
+// plus one for the memory cell it overwrote.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct History<W = isize> {
+    capacity: usize,
+    undos: VecDeque<Undo<W>>,
+}
+
+// The state an instruction changed, as it was before
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Undo<W> {
+    pub(crate) ip: usize,
+    pub(crate) relative_base: usize,
+    // (address, old value)
+    pub(crate) write: Option<(usize, W)>,
+    pub(crate) input: Option<W>,
+    pub(crate) output: bool,
+}
+
+impl<W> History<W> {
+    pub fn new(capacity: usize) -> History<W> {
+        History {
+            capacity,
+            undos: VecDeque::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // How many instructions can be undone
+    pub fn len(&self) -> usize {
+        self.undos.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.undos.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undos.clear();
+    }
+
+    pub(crate) fn push(&mut self, undo: Undo<W>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.undos.len() == self.capacity {
+            self.undos.pop_front();
+        }
+        self.undos.push_back(undo);
+    }
+}
+
+// Stepping back puts memory, ip, relative_base, and steps back as they were, and hands input back
+// to the input device and takes output back from the output device. It stops at the start of the
+// history, or at an instruction whose device can't take its value back, which is left as it was.
+// Any trace or profile isn't rewound.
+impl<I: InputSource<W>, O: OutputSink<W>, W: Word> Program<I, O, W> {
+    // Undo up to n instructions, returning how many were undone
+    pub fn step_back(&mut self, n: usize) -> usize {
+        (0..n).take_while(|_| self.undo().is_some()).count()
+    }
+
+    // Step back until the last n output values are undone, leaving ip at the instruction that wrote
+    // the earliest of them. Returns how many outputs were undone.
+    pub fn rewind_outputs(&mut self, n: usize) -> usize {
+        let mut outputs = 0;
+        while outputs < n {
+            match self.undo() {
+                Some(true) => outputs += 1,
+                Some(false) => {}
+                None => break,
+            }
+        }
+        outputs
+    }
+
+    // Undo the most recent instruction, returning whether it wrote output
+    fn undo(&mut self) -> Option<bool> {
+        let undo = self.history.as_mut()?.undos.pop_back()?;
+
+        // The devices go first, since they're the only part that can fail
+        let taken_back = match &undo.input {
+            Some(value) => self.input.unread(value.clone()),
+            None => !undo.output || self.output.unwrite(),
+        };
+        if !taken_back {
+            self.history.as_mut().unwrap().undos.push_back(undo);
+            return None;
+        }
+
+        if let Some((address, value)) = undo.write {
+            self.write_mem(address, value);
+        }
+        if undo.input.is_some() {
+            if let Some(recording) = &mut self.recording {
+                recording.unrecord();
+            }
+        }
+        self.ip = undo.ip;
+        self.relative_base = undo.relative_base;
+        self.steps = self.steps.saturating_sub(1);
+        self.halt_reason = None;
+        self.fault = None;
+
+        Some(undo.output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::device::{InputFn, OutputFn};
+    use crate::hooks::{HookAction, Hooks};
+    use crate::program::HaltReason::*;
+
+    // Output a running total of the inputs until a 0 is read
+    const TOTALS: &str = "
+                ARB #100
+        loop:   IN @0
+                JF @0, #done
+                ADD @0, @1, @1
+                OUT @1
+                JT #1, #loop
+        done:   HLT
+    ";
+
+    #[test]
+    fn test_step_back() {
+        let memory = assemble(TOTALS).unwrap();
+        let mut program = Program::new(memory.clone(), Some(vec![1, 2, 3, 0]));
+        program.history = Some(History::new(100));
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.output, vec![1, 3, 6]);
+
+        // Back to just before the 0 was read
+        assert_eq!(program.step_back(3), 3);
+        assert_eq!(program.halt_reason, None);
+        assert_eq!(program.input, vec![0]);
+        assert_eq!((program.ip, program.read_mem(100)), (2, 3));
+
+        // Back to just before the 6 was output
+        assert_eq!(program.rewind_outputs(1), 1);
+        assert_eq!((program.ip, program.output.clone()), (11, vec![1, 3]));
+
+        // Carry on from there with different input
+        program.input[0] = 4;
+        program.input.push_back(0);
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.output, vec![1, 3, 6, 10]);
+
+        // Rewinding all the way puts back every input and the original memory
+        let steps = program.steps;
+        assert_eq!(program.step_back(steps + 1), steps);
+        assert_eq!((program.ip, program.steps), (0, 0));
+        assert_eq!(program.input, vec![1, 2, 3, 4, 0]);
+        assert_eq!(program.output, vec![]);
+        assert_eq!(program.memory, memory);
+    }
+
+    #[test]
+    fn test_history_capacity() {
+        let memory = assemble(TOTALS).unwrap();
+        let mut program = Program::new(memory, Some(vec![1, 2, 3, 0]));
+        program.history = Some(History::new(5));
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.history.as_ref().unwrap().len(), 5);
+
+        // Only the last 5 instructions can be undone: HLT, JF, IN, JT, OUT
+        assert_eq!(program.step_back(10), 5);
+        assert_eq!(program.output, vec![1, 3]);
+        assert_eq!(program.rewind_outputs(1), 0);
+
+        let mut program = Program::new(vec![104, 1, 99], None);
+        program.history = Some(History::new(0));
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.step_back(1), 0);
+    }
+
+    #[test]
+    fn test_step_back_devices() {
+        let memory = assemble(TOTALS).unwrap();
+
+        // A closure can't be handed back what it supplied, so stepping back stops at the IN
+        let mut inputs = vec![0, 2, 1];
+        let mut program = Program::with_devices(memory.clone(), InputFn(|| inputs.pop()), vec![]);
+        program.history = Some(History::new(100));
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.step_back(3), 2);
+        assert_eq!((program.ip, program.read_mem(100)), (4, 0));
+        assert_eq!(program.history.as_ref().unwrap().len(), 12);
+        assert_eq!(program.step_back(1), 0);
+
+        // Nor can what it was given be taken back
+        let mut outputs = vec![];
+        let mut program = Program::with_devices(
+            memory,
+            VecDeque::from(vec![1, 0]),
+            OutputFn(|value| outputs.push(value)),
+        );
+        program.history = Some(History::new(100));
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.step_back(10), 4);
+        assert_eq!(program.ip, 13);
+        assert_eq!(program.input, vec![0]);
+        drop(program);
+        assert_eq!(outputs, vec![1]);
+    }
+
+    #[test]
+    fn test_step_back_substituted_input() {
+        // IN @5; HLT, with what IN stores made ten times the input
+        let mut hooks = Hooks::new();
+        hooks.on_write(5..6, |_, value| HookAction::Substitute(value * 10));
+        let mut program = Program::new(vec![3, 5, 99, 0, 0, 0], Some(vec![7]));
+        program.hooks = Some(hooks);
+        program.history = Some(History::new(100));
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.read_mem(5), 70);
+
+        // The input gets back what was read, not what was stored
+        assert_eq!(program.step_back(2), 2);
+        assert_eq!(program.input, vec![7]);
+        assert_eq!((program.ip, program.read_mem(5)), (0, 0));
+    }
+}
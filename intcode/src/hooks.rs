@@ -0,0 +1,265 @@
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::ops::{Bound, RangeBounds};
+
+use crate::device::{InputSource, OutputSink};
+use crate::error::IntcodeError;
+use crate::memory::PagedMemory;
+use crate::program::{Engine, Fetched, ParameterMode, ParameterUse, Program};
+use crate::word::Word;
+use ParameterMode::*;
+use ParameterUse::*;
+
+// Set Program::hooks to have callbacks run whenever the program reads or writes chosen addresses,
+// e.g. to watch a game's score, or to cheat at it. Each callback gets the address and the value
+// read or about to be written, and decides what happens to the access. Only parameters read and
+// written by instructions are hooked; instructions and their operands being fetched aren't, and
+// neither is anything done to memory from outside the program.
+//
+// Hooks run in the order they were added, each seeing what the one before it substituted, until
+// one vetoes.
+#[derive(Default)]
+pub struct Hooks<W = isize> {
+    hooks: Vec<Hook<W>>,
+}
+
+pub enum HookAction<W = isize> {
+    // Let the access go ahead as it is
+    Allow,
+    // Have the instruction read this value, or write it, instead
+    Substitute(W),
+    // Don't let it happen: a write is dropped, leaving memory as it was, and a read sees 0, as if
+    // the cell had never been written
+    Veto,
+}
+
+struct Hook<W> {
+    access: ParameterUse,
+    addresses: (Bound<usize>, Bound<usize>),
+    callback: Callback<W>,
+}
+
+type Callback<W> = Box<dyn FnMut(usize, &W) -> HookAction<W> + Send>;
+
+impl<W: Word> Hooks<W> {
+    pub fn new() -> Hooks<W> {
+        Hooks { hooks: vec![] }
+    }
+
+    pub fn on_read<R, F>(&mut self, addresses: R, callback: F) -> &mut Hooks<W>
+    where
+        R: RangeBounds<usize>,
+        F: FnMut(usize, &W) -> HookAction<W> + Send + 'static,
+    {
+        self.add(Read, addresses, callback)
+    }
+
+    pub fn on_write<R, F>(&mut self, addresses: R, callback: F) -> &mut Hooks<W>
+    where
+        R: RangeBounds<usize>,
+        F: FnMut(usize, &W) -> HookAction<W> + Send + 'static,
+    {
+        self.add(Write, addresses, callback)
+    }
+
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.hooks.clear();
+    }
+
+    fn add<R, F>(&mut self, access: ParameterUse, addresses: R, callback: F) -> &mut Hooks<W>
+    where
+        R: RangeBounds<usize>,
+        F: FnMut(usize, &W) -> HookAction<W> + Send + 'static,
+    {
+        self.hooks.push(Hook {
+            access,
+            addresses: (
+                addresses.start_bound().cloned(),
+                addresses.end_bound().cloned(),
+            ),
+            callback: Box::new(callback),
+        });
+        self
+    }
+
+    // What the access to address should read or write instead of value, or None if it's vetoed
+    pub(crate) fn run(&mut self, access: ParameterUse, address: usize, value: W) -> Option<W> {
+        let mut value = value;
+        for hook in &mut self.hooks {
+            if hook.access != access || !hook.addresses.contains(&address) {
+                continue;
+            }
+            match (hook.callback)(address, &value) {
+                HookAction::Allow => {}
+                HookAction::Substitute(substitute) => value = substitute,
+                HookAction::Veto => return None,
+            }
+        }
+
+        Some(value)
+    }
+}
+
+// Callbacks can't be looked into, so hooks are shown, and compared, by what they're on
+impl<W> Debug for Hooks<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.hooks.iter().map(|hook| (hook.access, hook.addresses)))
+            .finish()
+    }
+}
+
+impl<W> PartialEq for Hooks<W> {
+    fn eq(&self, other: &Hooks<W>) -> bool {
+        self.hooks.len() == other.hooks.len()
+            && self
+                .hooks
+                .iter()
+                .zip(&other.hooks)
+                .all(|(a, b)| (a.access, a.addresses) == (b.access, b.addresses))
+    }
+}
+
+impl<W> Eq for Hooks<W> {}
+
+// The engine a program runs on while it has hooks: another engine, with the hooks run over what it
+// reads and writes
+pub(crate) struct Hooked<'a, W, E> {
+    hooks: &'a mut Hooks<W>,
+    engine: &'a mut E,
+}
+
+impl<'a, W: Word, E: Engine<W>> Hooked<'a, W, E> {
+    pub(crate) fn new(hooks: &'a mut Hooks<W>, engine: &'a mut E) -> Hooked<'a, W, E> {
+        Hooked { hooks, engine }
+    }
+}
+
+impl<W: Word, E: Engine<W>> Engine<W> for Hooked<'_, W, E> {
+    fn fetch<I: InputSource<W>, O: OutputSink<W>>(
+        &mut self,
+        program: &Program<I, O, W>,
+    ) -> Result<Fetched<W>, IntcodeError> {
+        let mut fetched = self.engine.fetch(program)?;
+
+        // The params were fetched, so their addresses are known to be valid
+        for pat in fetched
+            .operation
+            .params_at
+            .iter()
+            .filter(|pat| pat.1 == Read)
+        {
+            let offset = program.read_mem(program.ip + 1 + pat.0).to_isize();
+            let address = match (fetched.param_modes[pat.0], offset) {
+                (PositionMode, Some(offset)) => offset as usize,
+                (RelativeMode, Some(offset)) => (program.relative_base as isize + offset) as usize,
+                _ => continue,
+            };
+            let param = &mut fetched.params[pat.0];
+            *param = self
+                .hooks
+                .run(Read, address, param.clone())
+                .unwrap_or_default();
+        }
+
+        Ok(fetched)
+    }
+
+    fn write(&mut self, memory: &mut PagedMemory<W>, address: usize, value: W) {
+        if let Some(value) = self.hooks.run(Write, address, value) {
+            self.engine.write(memory, address, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::block_cache::BlockCache;
+    use crate::program::HaltReason::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_hooks() {
+        // Add up the inputs until a 0, and output the total
+        let memory = assemble(
+            "
+            loop:   IN n                    ; 0
+                    ADD n, total, total     ; 2
+                    JT n, #loop             ; 6
+                    OUT total               ; 9
+                    HLT                     ; 11
+            n:      .data 0                 ; 12
+            total:  .data 0                 ; 13
+            ",
+        )
+        .unwrap();
+
+        for &cached in &[false, true] {
+            let seen = Arc::new(Mutex::new(vec![]));
+            let mut hooks = Hooks::new();
+            {
+                let seen = seen.clone();
+                hooks.on_write(13..=13, move |address, value: &isize| {
+                    seen.lock().unwrap().push((address, *value));
+                    HookAction::Allow
+                });
+            }
+            // Every input counts double, except that 5s are dropped
+            hooks
+                .on_write(12..13, |_, value| HookAction::Substitute(value * 2))
+                .on_write(..=12, |_, value| match value {
+                    10 => HookAction::Veto,
+                    _ => HookAction::Allow,
+                });
+
+            let mut program = Program::new(memory.clone(), Some(vec![1, 2, 5, 3, 0]));
+            program.hooks = Some(hooks);
+            if cached {
+                program.block_cache = Some(BlockCache::new());
+            }
+            assert_eq!(program.run(), Ok(Halted));
+            assert_eq!(program.output, vec![16]);
+            // The vetoed 5 left the input before it in n, so that was added again
+            assert_eq!(
+                *seen.lock().unwrap(),
+                vec![(13, 2), (13, 6), (13, 10), (13, 16), (13, 16)],
+            );
+        }
+    }
+
+    #[test]
+    fn test_hooks_read() {
+        // Output the same cell three times, once through each read mode that looks at memory
+        let memory = vec![4, 9, 109, 5, 204, 4, 4, 9, 99, 7];
+        let mut hooks = Hooks::new();
+        let mut reads = 0;
+        hooks.on_read(9..=9, move |_, value: &isize| {
+            reads += 1;
+            match reads {
+                1 => HookAction::Allow,
+                2 => HookAction::Substitute(value + 1),
+                _ => HookAction::Veto,
+            }
+        });
+
+        let mut program = Program::new(memory, None);
+        program.hooks = Some(hooks);
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(program.output, vec![7, 8, 0]);
+        assert_eq!(program.memory[9], 7);
+        assert_eq!(
+            format!("{:?}", program.hooks.unwrap()),
+            "[(Read, (Included(9), Included(9)))]"
+        );
+    }
+}
@@ -0,0 +1,55 @@
+pub use crate::ascii::AsciiSession;
+pub use crate::assembler::assemble;
+pub use crate::block_cache::BlockCache;
+pub use crate::control_flow::{BasicBlock, ControlFlowGraph, Exit};
+pub use crate::custom::CustomOpcodes;
+pub use crate::debugger::{Debugger, Stop};
+pub use crate::device::{AsciiInput, AsciiOutput, InputFn, InputSource, OutputFn, OutputSink};
+pub use crate::disassembler::{decode_at, disassemble, listing, source, Line, Operand};
+pub use crate::error::{
+    AssembleError, IntcodeError, ParseMemoryError, RecordingError, ReplayError, SnapshotError,
+    ThreadedError,
+};
+pub use crate::history::History;
+pub use crate::hooks::{HookAction, Hooks};
+pub use crate::memory::{MemoryStats, PagedMemory, PAGE_SIZE};
+pub use crate::network::{Network, NetworkStop, Packet, Routing};
+pub use crate::operation::Operation;
+pub use crate::profile::Profile;
+pub use crate::program::{
+    parse_memory, HaltReason, Input, InputQueue, Instruction, Limits, Memory, Opcode, Output,
+    ParamAt, ParameterMode, ParameterModes, ParameterUse, Program, MAX_PARAMS,
+};
+pub use crate::recording::{RecordedInput, Recording, RECORDING_VERSION};
+pub use crate::snapshot::{Snapshot, SNAPSHOT_VERSION};
+pub use crate::trace::{Trace, TraceEvent, TraceFilter, TraceSink};
+pub use crate::translate::{translate, translate_patchable};
+pub use crate::word::Word;
+
+#[cfg(feature = "bigint")]
+pub use num_bigint::BigInt;
+
+mod ascii;
+mod assembler;
+mod block_cache;
+mod control_flow;
+mod custom;
+mod debugger;
+mod device;
+#[cfg(test)]
+mod differential;
+mod disassembler;
+mod error;
+mod history;
+mod hooks;
+mod memory;
+mod network;
+mod operation;
+mod profile;
+mod program;
+mod recording;
+mod snapshot;
+mod threaded;
+mod trace;
+mod translate;
+mod word;
@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::iter;
+use std::mem;
+use std::ops::{Deref, Index, IndexMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::word::Word;
+
+pub const PAGE_SIZE: usize = 1024;
+
+// Pages below this are found by indexing straight into the page table. Anything higher (which
+// only a runaway program would touch) goes in a map, so the table never grows past 1.5MB (24 bytes
+// a page).
+const DIRECT_PAGES: usize = 1 << 16;
+
+// Pages hold PAGE_SIZE cells, except that the last page of the loaded image only holds as many as
+// the image needs, until something is written past them.
+//
+// Cloning a page shares its cells rather than copying them, and whichever copy is written to next
+// takes a copy of its own. So a snapshot only copies the pages written since the last one, and
+// branching from it copies nothing until the branch writes.
+enum Page<W> {
+    Owned(Box<[W]>),
+    Shared(Arc<[W]>),
+}
+
+// Sparse memory covering every address, split into fixed-size pages that are only allocated once
+// something is written to them. Reading a cell that was never written gives 0 without allocating.
+//
+// len() is the size of the image the program was loaded from. Cells past it can be read and
+// written like any other, but the program halts if ip runs past it.
+#[derive(Clone, Default)]
+pub struct PagedMemory<W = isize> {
+    len: usize,
+    // Page 0 is kept out of the table, so the cells most programs spend nearly all their time in
+    // are reached without looking anything up. Its slot in the table is always None.
+    first: Page<W>,
+    pages: Vec<Option<Page<W>>>,
+    far_pages: HashMap<usize, Page<W>>,
+    // What unallocated cells read as
+    zero: W,
+    log: WriteLog,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct MemoryStats {
+    // The size of the loaded image
+    pub len: usize,
+    pub pages: usize,
+    // Only what the pages themselves take up, not anything a word points to (like a BigInt's
+    // digits)
+    pub bytes: usize,
+}
+
+impl<W: Word> PagedMemory<W> {
+    pub fn new() -> PagedMemory<W> {
+        Default::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn read(&self, idx: usize) -> W {
+        self.cell(idx).clone()
+    }
+
+    #[inline]
+    pub fn write(&mut self, idx: usize, value: W) {
+        self.log.push(idx);
+        *self.cell_mut(idx) = value;
+    }
+
+    // A write that isn't logged, for when the block cache already knows about it
+    #[inline]
+    pub(crate) fn store(&mut self, idx: usize, value: W) {
+        *self.cell_mut(idx) = value;
+    }
+
+    // Start logging writes over, under a new stamp, and hand back the stamp along with what was
+    // logged under the old one. If that's no longer the log being kept (because it was given up on,
+    // or this memory was loaded or restored since), there's nothing to hand back.
+    pub(crate) fn restart_log(&mut self, stamp: usize) -> (usize, Option<Vec<usize>>) {
+        let old = mem::replace(
+            &mut self.log,
+            WriteLog {
+                stamp: LOG_STAMPS.fetch_add(1, Ordering::Relaxed),
+                addresses: vec![],
+            },
+        );
+        let writes = if old.stamp != 0 && old.stamp == stamp {
+            Some(old.addresses)
+        } else {
+            None
+        };
+
+        (self.log.stamp, writes)
+    }
+
+    // Like read(), but only for addresses inside the loaded image
+    #[inline]
+    pub fn get(&self, idx: usize) -> Option<W> {
+        if idx < self.len {
+            Some(self.read(idx))
+        } else {
+            None
+        }
+    }
+
+    // The loaded image, as it is now
+    pub fn to_vec(&self) -> Vec<W> {
+        (0..self.len).map(|idx| self.read(idx)).collect()
+    }
+
+    // (address, value) for every cell that isn't 0, in address order
+    pub fn nonzero_cells(&self) -> Vec<(usize, W)> {
+        let mut page_idxs: Vec<usize> = self.far_pages.keys().copied().collect();
+        page_idxs.sort_unstable();
+
+        let direct = self
+            .pages
+            .iter()
+            .enumerate()
+            .filter_map(|(page_idx, page)| page.as_ref().map(|page| (page_idx, page)));
+        let far = page_idxs
+            .into_iter()
+            .map(|page_idx| (page_idx, &self.far_pages[&page_idx]));
+
+        iter::once((0, &self.first))
+            .chain(direct)
+            .chain(far)
+            .flat_map(|(page_idx, page)| {
+                page.iter()
+                    .enumerate()
+                    .filter(move |(_, value)| **value != self.zero)
+                    .map(move |(offset, value)| (page_idx * PAGE_SIZE + offset, value.clone()))
+            })
+            .collect()
+    }
+
+    pub fn stats(&self) -> MemoryStats {
+        let pages: Vec<&Page<W>> = iter::once(&self.first)
+            .filter(|page| !page.is_empty())
+            .chain(self.pages.iter().flatten())
+            .chain(self.far_pages.values())
+            .collect();
+        let cells: usize = pages.iter().map(|page| page.len()).sum();
+
+        MemoryStats {
+            len: self.len,
+            pages: pages.len(),
+            bytes: cells * mem::size_of::<W>(),
+        }
+    }
+
+    #[inline]
+    fn cell(&self, idx: usize) -> &W {
+        match self.first.get(idx) {
+            Some(value) => value,
+            None => self.paged_cell(idx),
+        }
+    }
+
+    #[inline]
+    fn cell_mut(&mut self, idx: usize) -> &mut W {
+        if matches!(&self.first, Page::Owned(first) if idx < first.len()) {
+            match &mut self.first {
+                Page::Owned(first) => &mut first[idx],
+                Page::Shared(_) => unreachable!(),
+            }
+        } else {
+            self.paged_cell_mut(idx)
+        }
+    }
+
+    // Anything the first page doesn't already hold
+    #[inline(never)]
+    fn paged_cell(&self, idx: usize) -> &W {
+        self.page(idx / PAGE_SIZE)
+            .and_then(|page| page.get(idx % PAGE_SIZE))
+            .unwrap_or(&self.zero)
+    }
+
+    #[inline(never)]
+    fn paged_cell_mut(&mut self, idx: usize) -> &mut W {
+        let offset = idx % PAGE_SIZE;
+        &mut self.page_mut(idx / PAGE_SIZE).cells_mut(offset)[offset]
+    }
+
+    // The page table never reaches DIRECT_PAGES, so far pages only need checking on a miss
+    fn page(&self, page_idx: usize) -> Option<&Page<W>> {
+        if page_idx == 0 {
+            return Some(&self.first);
+        }
+        match self.pages.get(page_idx) {
+            Some(page) => page.as_ref(),
+            None if page_idx >= DIRECT_PAGES => self.far_pages.get(&page_idx),
+            None => None,
+        }
+    }
+
+    fn page_mut(&mut self, page_idx: usize) -> &mut Page<W> {
+        if page_idx == 0 {
+            &mut self.first
+        } else if page_idx < DIRECT_PAGES {
+            if page_idx >= self.pages.len() {
+                self.pages.resize_with(page_idx + 1, || None);
+            }
+            self.pages[page_idx].get_or_insert_with(new_page)
+        } else {
+            self.far_pages.entry(page_idx).or_insert_with(new_page)
+        }
+    }
+}
+
+// The log only grows so far before it's given up on and everything is taken to have changed
+const MAX_LOGGED_WRITES: usize = 1024;
+
+// Stamps are never reused, so a memory only ever matches the log that was started on it
+static LOG_STAMPS: AtomicUsize = AtomicUsize::new(1);
+
+// Addresses written through write() and IndexMut since a block cache last looked, so it can drop
+// what it decoded from them. A stamp of 0 means nothing's being logged. The block cache stores
+// through store() instead, since it sees its own writes.
+#[derive(Debug, Clone, Default)]
+struct WriteLog {
+    stamp: usize,
+    addresses: Vec<usize>,
+}
+
+impl WriteLog {
+    #[inline]
+    fn push(&mut self, address: usize) {
+        if self.stamp == 0 {
+            return;
+        }
+        if self.addresses.len() == MAX_LOGGED_WRITES {
+            *self = Default::default();
+        } else {
+            self.addresses.push(address);
+        }
+    }
+}
+
+// Allocated already zeroed, rather than built on the stack and copied over
+fn new_page<W: Word>() -> Page<W> {
+    Page::Owned(vec![W::default(); PAGE_SIZE].into_boxed_slice())
+}
+
+impl<W: Word> Page<W> {
+    // The cells, to write to. They're copied first if they're shared, or too short to reach offset.
+    fn cells_mut(&mut self, offset: usize) -> &mut [W] {
+        let copy = match self {
+            Page::Owned(cells) => offset >= cells.len(),
+            Page::Shared(_) => true,
+        };
+        if copy {
+            let mut cells = self.to_vec();
+            if offset >= cells.len() {
+                cells.resize(PAGE_SIZE, W::default());
+            }
+            *self = Page::Owned(cells.into_boxed_slice());
+        }
+
+        match self {
+            Page::Owned(cells) => cells,
+            Page::Shared(_) => unreachable!(),
+        }
+    }
+}
+
+impl<W> Deref for Page<W> {
+    type Target = [W];
+
+    #[inline]
+    fn deref(&self) -> &[W] {
+        match self {
+            Page::Owned(cells) => cells,
+            Page::Shared(cells) => cells,
+        }
+    }
+}
+
+impl<W: Clone> Clone for Page<W> {
+    fn clone(&self) -> Page<W> {
+        match self {
+            Page::Owned(cells) => Page::Shared(Arc::from(&cells[..])),
+            Page::Shared(cells) => Page::Shared(Arc::clone(cells)),
+        }
+    }
+}
+
+impl<W> Default for Page<W> {
+    fn default() -> Page<W> {
+        Page::Owned(Box::default())
+    }
+}
+
+impl<W: Word> From<Vec<W>> for PagedMemory<W> {
+    fn from(image: Vec<W>) -> PagedMemory<W> {
+        let mut memory = PagedMemory {
+            len: image.len(),
+            ..Default::default()
+        };
+
+        // Most programs fit in the first page, which takes over the image's allocation as it is
+        let mut image = image;
+        if image.len() > PAGE_SIZE {
+            let rest = image.split_off(PAGE_SIZE);
+            memory.pages = iter::once(None)
+                .chain(
+                    rest.chunks(PAGE_SIZE)
+                        .map(|chunk| Some(Page::Owned(chunk.to_vec().into_boxed_slice()))),
+                )
+                .collect();
+        }
+        memory.first = Page::Owned(image.into_boxed_slice());
+
+        memory
+    }
+}
+
+impl<W: Word> Index<usize> for PagedMemory<W> {
+    type Output = W;
+
+    fn index(&self, idx: usize) -> &W {
+        self.cell(idx)
+    }
+}
+
+impl<W: Word> IndexMut<usize> for PagedMemory<W> {
+    fn index_mut(&mut self, idx: usize) -> &mut W {
+        self.log.push(idx);
+        self.cell_mut(idx)
+    }
+}
+
+// Two memories are equal if every cell reads the same, however they happen to be paged
+impl<W: Word> PartialEq for PagedMemory<W> {
+    fn eq(&self, other: &PagedMemory<W>) -> bool {
+        self.len == other.len && self.nonzero_cells() == other.nonzero_cells()
+    }
+}
+
+impl<W: Word> Eq for PagedMemory<W> {}
+
+impl<W: Word> PartialEq<Vec<W>> for PagedMemory<W> {
+    fn eq(&self, other: &Vec<W>) -> bool {
+        self.len == other.len()
+            && other
+                .iter()
+                .enumerate()
+                .all(|(idx, value)| self.cell(idx) == value)
+            && self
+                .nonzero_cells()
+                .iter()
+                .all(|(address, _)| *address < self.len)
+    }
+}
+
+// The image, then any other cells that aren't 0
+impl<W: Word> Debug for PagedMemory<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let extra: Vec<(usize, W)> = self
+            .nonzero_cells()
+            .into_iter()
+            .filter(|(address, _)| *address >= self.len)
+            .collect();
+
+        f.debug_struct("PagedMemory")
+            .field("image", &self.to_vec())
+            .field("extra", &extra)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::Memory;
+
+    #[test]
+    fn test_paged_memory() {
+        let mut memory = PagedMemory::from((0..1500).collect::<Memory>());
+        assert_eq!(memory.len(), 1500);
+        assert_eq!(
+            memory.stats(),
+            MemoryStats {
+                len: 1500,
+                pages: 2,
+                bytes: 1500 * 8,
+            },
+        );
+        assert_eq!((memory.read(1499), memory[1024]), (1499, 1024));
+        assert_eq!((memory.get(1499), memory.get(1500)), (Some(1499), None));
+
+        // Reading anywhere never allocates
+        for &idx in &[1500, 5000, 1 << 30, usize::MAX] {
+            assert_eq!((memory.read(idx), memory[idx]), (0, 0));
+        }
+        assert_eq!(memory.stats().pages, 2);
+
+        // Writes past the image fill out its last page, and anywhere else allocate one page each
+        memory.write(1500, -1);
+        assert_eq!(memory.stats().bytes, 2 * PAGE_SIZE * 8);
+        memory[PAGE_SIZE * 3 + 7] = -2;
+        memory.write(usize::MAX, -3);
+        assert_eq!(memory.stats().pages, 4);
+        assert_eq!(memory.len(), 1500);
+        assert_eq!(
+            (
+                memory.read(1500),
+                memory.read(PAGE_SIZE * 3 + 7),
+                memory[usize::MAX]
+            ),
+            (-1, -2, -3),
+        );
+        assert_eq!(
+            memory.nonzero_cells()[1498..],
+            [
+                (1499, 1499),
+                (1500, -1),
+                (PAGE_SIZE * 3 + 7, -2),
+                (usize::MAX, -3),
+            ],
+        );
+
+        // The same goes for an image that doesn't fill the first page
+        let mut memory: PagedMemory = PagedMemory::from(vec![1, 2, 3]);
+        memory.write(500, 4);
+        assert_eq!(memory.stats().bytes, PAGE_SIZE * 8);
+        assert_eq!((memory.read(2), memory[500], memory.len()), (3, 4, 3));
+    }
+
+    #[test]
+    fn test_paged_memory_shared() {
+        let shared =
+            |a: &PagedMemory, b: &PagedMemory, page_idx| match (a.page(page_idx), b.page(page_idx))
+            {
+                (Some(Page::Shared(a)), Some(Page::Shared(b))) => Arc::ptr_eq(a, b),
+                _ => false,
+            };
+
+        let mut memory = PagedMemory::from((0..1500).collect::<Memory>());
+        let snapshot = memory.clone();
+        let mut branch = snapshot.clone();
+        assert!(shared(&snapshot, &branch, 0) && shared(&snapshot, &branch, 1));
+
+        // Each copy only takes a page of its own once it writes to it
+        branch.write(3, -1);
+        branch[PAGE_SIZE + 4] = -2;
+        assert!(!shared(&snapshot, &branch, 0) && !shared(&snapshot, &branch, 1));
+        let mut branch = snapshot.clone();
+        branch.write(1499, -3);
+        assert!(shared(&snapshot, &branch, 0) && !shared(&snapshot, &branch, 1));
+        memory.write(3, -4);
+
+        assert_eq!(
+            (
+                memory[3],
+                snapshot[3],
+                branch[3],
+                branch[1499],
+                snapshot[1499]
+            ),
+            (-4, 3, 3, -3, 1499),
+        );
+        assert_eq!(snapshot, (0..1500).collect::<Memory>());
+
+        // Writing past a shared page's end still fills it out
+        let mut short: PagedMemory = PagedMemory::from(vec![1, 2, 3]);
+        let copy = short.clone();
+        short.write(500, 4);
+        assert_eq!((short[2], short[500], copy[500]), (3, 4, 0));
+        assert_eq!(copy.stats().bytes, 3 * 8);
+    }
+
+    #[test]
+    fn test_paged_memory_eq() {
+        let mut memory: PagedMemory = PagedMemory::from(vec![1, 2, 3]);
+        assert_eq!(memory, vec![1, 2, 3]);
+        assert_ne!(memory, vec![1, 2]);
+
+        // A page full of zeros is the same as a page that was never allocated
+        memory.write(5000, 0);
+        assert_eq!(memory, vec![1, 2, 3]);
+        memory.write(5000, 4);
+        assert_ne!(memory, vec![1, 2, 3]);
+        assert_eq!(
+            format!("{:?}", memory),
+            "PagedMemory { image: [1, 2, 3], extra: [(5000, 4)] }",
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+use std::mem;
+
+use crate::error::IntcodeError;
+use crate::program::{HaltReason, Limits, Program};
+use crate::word::Word;
+use HaltReason::*;
+
+// Programs on a network read from a queue the network fills, and write to a Vec it empties
+pub(crate) type Node<W> = Program<VecDeque<W>, Vec<W>, W>;
+
+// Several programs run together, taking turns in order, with what each one outputs handed on to
+// others as their input. Day 7's feedback loop, say, is a ring of amplifiers:
+//
+//   let mut network = Network::new(amplifiers, Routing::Ring);
+//   network.programs[0].input.push_back(0);
+//   network.run()?;
+//
+// A program that faults stops the whole network with its error, and is left with its fault set to
+// tell which one it was.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Network<W: Word = isize> {
+    pub programs: Vec<Node<W>>,
+    pub routing: Routing,
+    // Given to a program that needs input when none has been sent to it, once each turn. With
+    // None, the program waits until something is sent.
+    pub idle_input: Option<W>,
+    // Caps on each turn. Without a step limit, a program that never waits for input keeps the
+    // others from ever running again.
+    pub turn_limits: Limits,
+    // Packets sent to addresses no program is at, in the order they were sent
+    pub unrouted: Vec<Packet<W>>,
+    // Full rounds of turns taken, over every run
+    pub rounds: usize,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Routing {
+    // Each program's output goes to the next one's input, and the last one's stays in its output
+    Pipeline,
+    // Like Pipeline, with the last program's output going back round to the first
+    Ring,
+    // Programs output packets of three values: an address, then x and y, which are sent on to the
+    // input of the program at that address (its index in programs)
+    Packets,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Packet<W = isize> {
+    pub address: W,
+    pub x: W,
+    pub y: W,
+}
+
+// Why Network::run() handed control back
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NetworkStop {
+    // Every program has halted
+    Halted,
+    // A whole round went by without anything being sent: every program was waiting for input it
+    // didn't get, apart from its idle input, or had halted
+    Idle,
+    // The condition given to run_until() held
+    Stopped,
+}
+
+impl<W: Word> Network<W> {
+    pub fn new(programs: Vec<Node<W>>, routing: Routing) -> Network<W> {
+        Network {
+            programs,
+            routing,
+            idle_input: None,
+            turn_limits: Limits::default(),
+            unrouted: vec![],
+            rounds: 0,
+        }
+    }
+
+    // Give every program turns until they've all halted or the network is idle
+    pub fn run(&mut self) -> Result<NetworkStop, IntcodeError> {
+        self.run_until(|_| false)
+    }
+
+    // Like run(), but also stop as soon as stop returns true. It's checked after every turn.
+    pub fn run_until<F>(&mut self, mut stop: F) -> Result<NetworkStop, IntcodeError>
+    where
+        F: FnMut(&Network<W>) -> bool,
+    {
+        loop {
+            let mut idle = true;
+            for idx in 0..self.programs.len() {
+                idle &= self.take_turn(idx)?;
+                if stop(self) {
+                    return Ok(NetworkStop::Stopped);
+                }
+            }
+            self.rounds += 1;
+
+            if self.programs.iter().all(|p| p.halt_reason == Some(Halted)) {
+                return Ok(NetworkStop::Halted);
+            }
+            if idle {
+                return Ok(NetworkStop::Idle);
+            }
+        }
+    }
+
+    // Run a program until it halts, waits for input, or reaches a turn limit, then send on what
+    // it output. Returns whether the turn was idle: the program had been sent nothing to read,
+    // output nothing, and ended up waiting for more input or halted.
+    fn take_turn(&mut self, idx: usize) -> Result<bool, IntcodeError> {
+        let program = &mut self.programs[idx];
+        if program.halt_reason == Some(Halted) {
+            return Ok(true);
+        }
+
+        let had_input = !program.input.is_empty();
+        let output_len = program.output.len();
+        let mut given_idle_input = false;
+        let halt_reason = loop {
+            match program.run_with_limits(self.turn_limits)? {
+                NeedsInput if !given_idle_input && self.idle_input.is_some() => {
+                    program.input.push_back(self.idle_input.clone().unwrap());
+                    given_idle_input = true;
+                }
+                halt_reason => break halt_reason,
+            }
+        };
+        let wrote_output = program.output.len() > output_len;
+
+        self.route(idx);
+
+        Ok(!had_input && !wrote_output && (halt_reason == NeedsInput || halt_reason == Halted))
+    }
+
+    pub(crate) fn route(&mut self, from: usize) {
+        let last = from + 1 == self.programs.len();
+        let to = match self.routing {
+            Routing::Pipeline if last => return,
+            Routing::Ring if last => 0,
+            Routing::Pipeline | Routing::Ring => from + 1,
+            Routing::Packets => return self.route_packets(from),
+        };
+
+        let output = mem::take(&mut self.programs[from].output);
+        self.programs[to].input.extend(output);
+    }
+
+    // Only whole packets are sent; the start of one that's only partly output waits for the rest
+    fn route_packets(&mut self, from: usize) {
+        let output = &mut self.programs[from].output;
+        let whole_len = output.len() - output.len() % 3;
+        let sent: Vec<W> = output.drain(..whole_len).collect();
+
+        for values in sent.chunks(3) {
+            let packet = Packet {
+                address: values[0].clone(),
+                x: values[1].clone(),
+                y: values[2].clone(),
+            };
+            let to = packet
+                .address
+                .to_isize()
+                .filter(|&address| 0 <= address && (address as usize) < self.programs.len());
+            match to {
+                Some(to) => {
+                    let input = &mut self.programs[to as usize].input;
+                    input.push_back(packet.x);
+                    input.push_back(packet.y);
+                }
+                None => self.unrouted.push(packet),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_network_ring() {
+        // The day 7 feedback loop example
+        let memory = vec![
+            3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1,
+            28, 1005, 28, 6, 99, 0, 0, 5,
+        ];
+        let amplifiers = [9, 8, 7, 6, 5]
+            .iter()
+            .map(|&phase| Program::new(memory.clone(), Some(vec![phase])))
+            .collect();
+
+        let mut network = Network::new(amplifiers, Routing::Ring);
+        network.programs[0].input.push_back(0);
+        assert_eq!(network.run(), Ok(NetworkStop::Halted));
+        assert_eq!(network.programs[0].input, vec![139629729]);
+        assert_eq!(network.rounds, 5);
+
+        // Had they been lined up instead, the first pass would have been all there was
+        let amplifiers = [9, 8, 7, 6, 5]
+            .iter()
+            .map(|&phase| Program::new(memory.clone(), Some(vec![phase])))
+            .collect();
+        let mut network = Network::new(amplifiers, Routing::Pipeline);
+        network.programs[0].input.push_back(0);
+        assert_eq!(network.run(), Ok(NetworkStop::Idle));
+        assert_eq!(network.programs[4].output, vec![129]);
+    }
+
+    #[test]
+    fn test_network_packets() {
+        // Send 1 two numbers, and something to nowhere, then pass on whatever comes back
+        let sender = assemble(
+            "
+                    OUT #1
+                    OUT #5
+                    OUT #6
+                    OUT #9
+                    OUT #1
+                    OUT #2
+                    IN x
+                    IN y
+                    OUT #255
+                    OUT x
+                    OUT y
+                    HLT
+            x:      .data 0
+            y:      .data 0
+            ",
+        )
+        .unwrap();
+        // Send 0 back the product of the two numbers it's sent, one value at a time
+        let multiplier = assemble(
+            "
+                    IN x
+                    IN y
+                    MUL x, y, x
+                    OUT #0
+                    OUT x
+                    OUT #0
+                    HLT
+            x:      .data 0
+            y:      .data 0
+            ",
+        )
+        .unwrap();
+
+        let mut network = Network::new(
+            vec![Program::new(sender, None), Program::new(multiplier, None)],
+            Routing::Packets,
+        );
+        // Make every program output a value at a time, so packets arrive in pieces
+        network.turn_limits.steps = Some(1);
+
+        assert_eq!(
+            network.run_until(|network| !network.unrouted.is_empty()),
+            Ok(NetworkStop::Stopped)
+        );
+        assert_eq!(network.unrouted.len(), 1);
+        assert_eq!(network.programs[0].ip, 12);
+
+        assert_eq!(network.run(), Ok(NetworkStop::Halted));
+        assert_eq!(
+            network.unrouted,
+            vec![
+                Packet {
+                    address: 9,
+                    x: 1,
+                    y: 2,
+                },
+                Packet {
+                    address: 255,
+                    x: 30,
+                    y: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_network_idle() {
+        // Echo every value read, apart from the -1s read while there's nothing to do
+        let memory = assemble(
+            "
+            loop:   IN x
+                    EQ x, #-1, idle
+                    JT idle, #loop
+                    OUT x
+                    JT #1, #loop
+            x:      .data 0
+            idle:   .data 0
+            ",
+        )
+        .unwrap();
+
+        let mut network = Network::new(vec![Program::new(memory, None)], Routing::Pipeline);
+        network.idle_input = Some(-1);
+        assert_eq!(network.run(), Ok(NetworkStop::Idle));
+        assert_eq!(network.rounds, 1);
+
+        network.programs[0].input.push_back(7);
+        assert_eq!(network.run(), Ok(NetworkStop::Idle));
+        assert_eq!(network.programs[0].output, vec![7]);
+        assert_eq!(network.rounds, 3);
+        assert_eq!(network.programs[0].steps, 14);
+    }
+
+    #[test]
+    fn test_network_fault() {
+        // The second program writes what the first sends it over its next instruction
+        let programs = vec![
+            Program::new(vec![104, 12, 99], None),
+            Program::new(vec![3, 2, 99], None),
+        ];
+        let mut network = Network::new(programs, Routing::Pipeline);
+        let err = IntcodeError::UnknownOpcode {
+            ip: 2,
+            instruction: 12,
+        };
+        assert_eq!(network.run(), Err(err.clone()));
+        assert_eq!(network.programs[0].fault, None);
+        assert_eq!(network.programs[1].fault, Some(err));
+        assert_eq!(network.programs[1].halt_reason, None);
+    }
+}
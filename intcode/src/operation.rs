@@ -0,0 +1,109 @@
+use crate::program::{Opcode, ParamAt, ParameterUse::*};
+
+// Everything needed to decode an instruction: its mnemonic and the parameters it takes. This table
+// is the single source of truth for both Program::run and the disassembler.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Operation {
+    pub opcode: Opcode,
+    pub mnemonic: &'static str,
+    pub params_at: &'static [ParamAt],
+}
+
+pub const ADD: Operation = Operation {
+    opcode: 1,
+    mnemonic: "ADD",
+    params_at: &[ParamAt(0, Read), ParamAt(1, Read), ParamAt(2, Write)],
+};
+
+pub const MUL: Operation = Operation {
+    opcode: 2,
+    mnemonic: "MUL",
+    params_at: &[ParamAt(0, Read), ParamAt(1, Read), ParamAt(2, Write)],
+};
+
+pub const IN: Operation = Operation {
+    opcode: 3,
+    mnemonic: "IN",
+    params_at: &[ParamAt(0, Write)],
+};
+
+pub const OUT: Operation = Operation {
+    opcode: 4,
+    mnemonic: "OUT",
+    params_at: &[ParamAt(0, Read)],
+};
+
+pub const JT: Operation = Operation {
+    opcode: 5,
+    mnemonic: "JT",
+    params_at: &[ParamAt(0, Read), ParamAt(1, Read)],
+};
+
+pub const JF: Operation = Operation {
+    opcode: 6,
+    mnemonic: "JF",
+    params_at: &[ParamAt(0, Read), ParamAt(1, Read)],
+};
+
+pub const LT: Operation = Operation {
+    opcode: 7,
+    mnemonic: "LT",
+    params_at: &[ParamAt(0, Read), ParamAt(1, Read), ParamAt(2, Write)],
+};
+
+pub const EQ: Operation = Operation {
+    opcode: 8,
+    mnemonic: "EQ",
+    params_at: &[ParamAt(0, Read), ParamAt(1, Read), ParamAt(2, Write)],
+};
+
+pub const ARB: Operation = Operation {
+    opcode: 9,
+    mnemonic: "ARB",
+    params_at: &[ParamAt(0, Read)],
+};
+
+pub const HLT: Operation = Operation {
+    opcode: 99,
+    mnemonic: "HLT",
+    params_at: &[],
+};
+
+pub const OPERATIONS: [&Operation; 10] = [&ADD, &MUL, &IN, &OUT, &JT, &JF, &LT, &EQ, &ARB, &HLT];
+
+impl Operation {
+    #[inline]
+    pub fn from_opcode(opcode: Opcode) -> Option<&'static Operation> {
+        match opcode {
+            1 => Some(&ADD),
+            2 => Some(&MUL),
+            3 => Some(&IN),
+            4 => Some(&OUT),
+            5 => Some(&JT),
+            6 => Some(&JF),
+            7 => Some(&LT),
+            8 => Some(&EQ),
+            9 => Some(&ARB),
+            99 => Some(&HLT),
+            _ => None,
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<&'static Operation> {
+        OPERATIONS
+            .iter()
+            .find(|op| op.mnemonic.eq_ignore_ascii_case(mnemonic))
+            .copied()
+    }
+
+    // The number of memory cells the instruction occupies, including the instruction itself
+    #[inline]
+    pub fn size(&self) -> usize {
+        1 + self.params_at.len()
+    }
+
+    #[inline]
+    pub fn is_jump(&self) -> bool {
+        self.opcode == JT.opcode || self.opcode == JF.opcode
+    }
+}
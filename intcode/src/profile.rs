@@ -0,0 +1,236 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+
+use crate::disassembler::{disassemble, listing};
+use crate::operation::Operation;
+use crate::program::{Memory, Opcode, ParameterMode};
+use ParameterMode::*;
+
+// Set Program::profile to count where the program spends its time. Like a trace, only instructions
+// that complete are counted.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Profile {
+    pub steps: usize,
+    // How many times the instruction at each address was executed
+    pub ips: BTreeMap<usize, usize>,
+    pub opcodes: BTreeMap<Opcode, usize>,
+    // How many parameters were read or written in each mode
+    pub modes: BTreeMap<ParameterMode, usize>,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Default::default()
+    }
+
+    pub(crate) fn record(&mut self, ip: usize, opcode: Opcode, param_modes: &[ParameterMode]) {
+        self.steps += 1;
+        *self.ips.entry(ip).or_insert(0) += 1;
+        *self.opcodes.entry(opcode).or_insert(0) += 1;
+        for &mode in param_modes {
+            *self.modes.entry(mode).or_insert(0) += 1;
+        }
+    }
+
+    // (address, count), most executed first, then in address order
+    pub fn hot_ips(&self) -> Vec<(usize, usize)> {
+        let mut ips: Vec<(usize, usize)> = self.ips.iter().map(|(&ip, &n)| (ip, n)).collect();
+        ips.sort_by_key(|&(ip, n)| (Reverse(n), ip));
+        ips
+    }
+
+    // Every count, most common first, with its share of its section's total, e.g.
+    //   steps: 9
+    //   opcodes:
+    //     ADD       2   22.2%
+    //   ...
+    //   ips:
+    //     6         2   22.2%
+    pub fn report(&self) -> String {
+        let mut opcodes: Vec<(String, usize)> = self
+            .opcodes
+            .iter()
+            .map(|(&opcode, &n)| (mnemonic(opcode), n))
+            .collect();
+        opcodes.sort_by_key(|&(_, n)| Reverse(n));
+        let mut modes: Vec<(String, usize)> = self
+            .modes
+            .iter()
+            .map(|(&mode, &n)| (mode_name(mode).to_string(), n))
+            .collect();
+        modes.sort_by_key(|&(_, n)| Reverse(n));
+        let ips: Vec<(String, usize)> = self
+            .hot_ips()
+            .into_iter()
+            .map(|(ip, n)| (ip.to_string(), n))
+            .collect();
+
+        let mut report = format!("steps: {}\n", self.steps);
+        for (title, counts) in &[("opcodes", opcodes), ("modes", modes), ("ips", ips)] {
+            let total: usize = counts.iter().map(|(_, n)| n).sum();
+            report.push_str(&format!("{}:\n", title));
+            for (name, n) in counts {
+                report.push_str(&format!(
+                    "  {:<9} {:>9} {:>6.1}%\n",
+                    name,
+                    n,
+                    100.0 * *n as f64 / total as f64,
+                ));
+            }
+        }
+
+        report
+    }
+
+    // One "kind key count" line per count, in key order rather than by count, so that the profiles
+    // of two runs can be diffed
+    pub fn write_flat<W: Write>(&self, mut output: W) -> io::Result<()> {
+        writeln!(output, "steps {}", self.steps)?;
+        for (opcode, n) in &self.opcodes {
+            writeln!(output, "opcode {} {}", opcode, n)?;
+        }
+        for (&mode, n) in &self.modes {
+            writeln!(output, "mode {} {}", mode_name(mode), n)?;
+        }
+        for (ip, n) in &self.ips {
+            writeln!(output, "ip {} {}", ip, n)?;
+        }
+
+        Ok(())
+    }
+
+    // The disassembler's listing with each line's count in front of it (blank if never executed).
+    // Counts for addresses the listing doesn't start a line at, which self-modifying code can
+    // produce, aren't shown.
+    pub fn listing(&self, memory: &Memory) -> String {
+        let counts: Vec<String> = disassemble(memory)
+            .iter()
+            .map(|line| match self.ips.get(&line.address()) {
+                Some(n) => n.to_string(),
+                None => String::new(),
+            })
+            .collect();
+        let count_width = counts.iter().map(|c| c.len()).max().unwrap_or(0);
+
+        listing(memory)
+            .lines()
+            .zip(counts)
+            .map(|(line, count)| {
+                format!(
+                    "{:>count_width$}  {}\n",
+                    count,
+                    line,
+                    count_width = count_width
+                )
+            })
+            .collect()
+    }
+}
+
+fn mnemonic(opcode: Opcode) -> String {
+    match Operation::from_opcode(opcode) {
+        Some(operation) => operation.mnemonic.to_string(),
+        None => opcode.to_string(),
+    }
+}
+
+fn mode_name(mode: ParameterMode) -> &'static str {
+    match mode {
+        PositionMode => "position",
+        ImmediateMode => "immediate",
+        RelativeMode => "relative",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::program::{HaltReason, Program};
+
+    #[test]
+    fn test_profile() {
+        // Count down from 2
+        let memory = assemble(
+            "
+                    ARB #100
+                    IN @0
+            loop:   ADD @0, #-1, @0
+                    JT @0, #loop
+                    HLT
+            ",
+        )
+        .unwrap();
+        let mut program = Program::new(memory.clone(), Some(vec![2]));
+        program.profile = Some(Profile::new());
+        assert_eq!(program.run(), Ok(HaltReason::Halted));
+        let profile = program.profile.unwrap();
+
+        assert_eq!(profile.steps, 7);
+        assert_eq!(
+            profile.hot_ips(),
+            vec![(4, 2), (8, 2), (0, 1), (2, 1), (11, 1)]
+        );
+        assert_eq!(
+            profile.report(),
+            [
+                "steps: 7",
+                "opcodes:",
+                "  ADD               2   28.6%",
+                "  JT                2   28.6%",
+                "  IN                1   14.3%",
+                "  ARB               1   14.3%",
+                "  HLT               1   14.3%",
+                "modes:",
+                "  relative          7   58.3%",
+                "  immediate         5   41.7%",
+                "ips:",
+                "  4                 2   28.6%",
+                "  8                 2   28.6%",
+                "  0                 1   14.3%",
+                "  2                 1   14.3%",
+                "  11                1   14.3%",
+                "",
+            ]
+            .join("\n"),
+        );
+
+        let mut flat = vec![];
+        profile.write_flat(&mut flat).unwrap();
+        assert_eq!(
+            String::from_utf8(flat).unwrap(),
+            [
+                "steps 7",
+                "opcode 1 2",
+                "opcode 3 1",
+                "opcode 5 2",
+                "opcode 9 1",
+                "opcode 99 1",
+                "mode immediate 5",
+                "mode relative 7",
+                "ip 0 1",
+                "ip 2 1",
+                "ip 4 2",
+                "ip 8 2",
+                "ip 11 1",
+                "",
+            ]
+            .join("\n"),
+        );
+
+        assert_eq!(
+            profile.listing(&memory),
+            [
+                "1   0  109 100       ARB #100",
+                "1   2  203 0         IN @0",
+                "2   4  21201 0 -1 0  ADD @0, #-1, @0",
+                "2   8  1205 0 4      JT @0, #4            ; -> 4",
+                "1  11  99            HLT",
+                "",
+            ]
+            .join("\n"),
+        );
+    }
+}
@@ -0,0 +1,1148 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::block_cache::BlockCache;
+use crate::custom::CustomOpcodes;
+use crate::device::{InputSource, OutputSink};
+use crate::disassembler::Operand;
+use crate::error::{IntcodeError, ParseMemoryError};
+use crate::history::{History, Undo};
+use crate::hooks::{Hooked, Hooks};
+use crate::memory::PagedMemory;
+use crate::operation::Operation;
+use crate::profile::Profile;
+use crate::recording::Recording;
+use crate::trace::{Trace, TraceEvent};
+use crate::word::Word;
+use HaltReason::*;
+use ParameterMode::*;
+use ParameterUse::*;
+
+// The input and output devices default to a queue of input values and a Vec of every value output,
+// but anything implementing InputSource or OutputSink can be plugged in with with_devices(). Words
+// are isize unless another Word type is picked, e.g. Program::<_, _, i128>::from_words().
+#[derive(Debug, Eq, PartialEq)]
+pub struct Program<I = InputQueue, O = Output, W: Word = isize> {
+    pub memory: PagedMemory<W>,
+    pub ip: usize,
+    pub relative_base: usize,
+    // Instructions executed so far, over every run
+    pub steps: usize,
+    pub input: I,
+    pub output: O,
+    pub halt_reason: Option<HaltReason>,
+    // What the last run faulted on, if it did, in which case halt_reason is None
+    pub fault: Option<IntcodeError>,
+    pub trace: Option<Trace<W>>,
+    pub profile: Option<Profile>,
+    pub history: Option<History<W>>,
+    pub block_cache: Option<BlockCache<W>>,
+    pub hooks: Option<Hooks<W>>,
+    pub custom_opcodes: Option<CustomOpcodes<W>>,
+    pub recording: Option<Recording<W>>,
+}
+
+pub type Memory = Vec<isize>;
+pub type Input = Vec<isize>;
+pub type InputQueue = VecDeque<isize>;
+pub type Output = Vec<isize>;
+pub type Instruction = isize;
+pub type Opcode = isize;
+pub type ParameterModes = [ParameterMode; MAX_PARAMS];
+
+// No instruction takes more than three parameters
+pub const MAX_PARAMS: usize = 3;
+
+// The place value of each parameter's mode digit; the one past the end marks where an instruction
+// with every parameter's mode given would end
+pub(crate) const PARAM_MODE_PLACES: [isize; MAX_PARAMS + 1] = [100, 1000, 10000, 100000];
+
+// The clock is only checked this often, since reading it costs more than most instructions
+const STEPS_PER_TIME_CHECK: usize = 1024;
+
+// Parse a program in the puzzle input format: comma-separated values, with surrounding whitespace
+// ignored
+pub fn parse_memory(text: &str) -> Result<Memory, ParseMemoryError> {
+    text.trim()
+        .split(',')
+        .enumerate()
+        .map(|(address, value)| {
+            value.trim().parse().map_err(|_| ParseMemoryError {
+                address,
+                value: value.to_string(),
+            })
+        })
+        .collect()
+}
+
+// Why the most recent call to run() (or one of its variants) handed control back to the caller.
+// Running again after StepLimitReached or TimeLimitReached carries on where the program left off.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum HaltReason {
+    Halted,
+    NeedsInput,
+    OutputReady,
+    StepLimitReached,
+    TimeLimitReached,
+}
+
+// Caps on a single call to run_with_limits(); None means no limit. The time limit is checked every
+// STEPS_PER_TIME_CHECK instructions, so it can be overshot by a little.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct Limits {
+    pub steps: Option<usize>,
+    pub time: Option<Duration>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ParamAt(pub usize, pub ParameterUse);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ParameterUse {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ParameterMode {
+    PositionMode,
+    ImmediateMode,
+    RelativeMode,
+}
+
+impl Program {
+    pub fn new(memory: Memory, input: Option<Input>) -> Program {
+        Program {
+            memory: memory.into(),
+            ip: 0,
+            relative_base: 0,
+            steps: 0,
+            input: VecDeque::from(input.unwrap_or(vec![])),
+            output: vec![],
+            halt_reason: None,
+            fault: None,
+            trace: None,
+            profile: None,
+            history: None,
+            block_cache: None,
+            hooks: None,
+            custom_opcodes: None,
+            recording: None,
+        }
+    }
+
+    #[inline]
+    fn parse_param_mode(instruction: Instruction, idx: usize) -> Result<ParameterMode, isize> {
+        use ParameterMode::*;
+
+        // The hundreds digit is the mode of the first parameter, the thousands digit the second...
+        let mode = (instruction / PARAM_MODE_PLACES[idx]) % 10;
+
+        match mode {
+            0 => Ok(PositionMode),
+            1 => Ok(ImmediateMode),
+            2 => Ok(RelativeMode),
+            _ => Err(mode),
+        }
+    }
+
+    // The inverse of parse_param_mode, for building instructions
+    pub(crate) fn param_mode_digit(mode: ParameterMode) -> isize {
+        match mode {
+            PositionMode => 0,
+            ImmediateMode => 1,
+            RelativeMode => 2,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn parse_opcode(instruction: Instruction) -> Opcode {
+        // Negative instructions are never valid; pass them through so they hit the unknown arm
+        if instruction < 0 {
+            return instruction;
+        }
+
+        instruction % 100
+    }
+
+    #[inline]
+    pub(crate) fn parse_param_modes(
+        instruction: Instruction,
+        n_params: usize,
+    ) -> Result<ParameterModes, isize> {
+        let mut param_modes = [PositionMode; MAX_PARAMS];
+
+        for (idx, param_mode) in param_modes.iter_mut().enumerate().take(n_params) {
+            *param_mode = Program::parse_param_mode(instruction, idx)?;
+        }
+
+        Ok(param_modes)
+    }
+}
+
+impl<W: Word> Program<VecDeque<W>, Vec<W>, W> {
+    // Like new(), for any word type
+    pub fn from_words(memory: Vec<W>, input: Option<Vec<W>>) -> Program<VecDeque<W>, Vec<W>, W> {
+        Program::with_devices(memory, VecDeque::from(input.unwrap_or(vec![])), vec![])
+    }
+}
+
+impl<I: InputSource<W>, O: OutputSink<W>, W: Word> Program<I, O, W> {
+    pub fn with_devices(memory: Vec<W>, input: I, output: O) -> Program<I, O, W> {
+        Program {
+            memory: memory.into(),
+            ip: 0,
+            relative_base: 0,
+            steps: 0,
+            input,
+            output,
+            halt_reason: None,
+            fault: None,
+            trace: None,
+            profile: None,
+            history: None,
+            block_cache: None,
+            hooks: None,
+            custom_opcodes: None,
+            recording: None,
+        }
+    }
+
+    // Move the program over to other devices, handing back the ones it had
+    pub fn replace_devices<J, P>(self, input: J, output: P) -> (Program<J, P, W>, I, O)
+    where
+        J: InputSource<W>,
+        P: OutputSink<W>,
+    {
+        let program = Program {
+            memory: self.memory,
+            ip: self.ip,
+            relative_base: self.relative_base,
+            steps: self.steps,
+            input,
+            output,
+            halt_reason: self.halt_reason,
+            fault: self.fault,
+            trace: self.trace,
+            profile: self.profile,
+            history: self.history,
+            block_cache: self.block_cache,
+            hooks: self.hooks,
+            custom_opcodes: self.custom_opcodes,
+            recording: self.recording,
+        };
+
+        (program, self.input, self.output)
+    }
+
+    pub fn read_mem(&self, idx: usize) -> W {
+        self.memory.read(idx)
+    }
+
+    pub fn write_mem(&mut self, idx: usize, value: W) {
+        match &mut self.block_cache {
+            Some(block_cache) => {
+                self.memory.store(idx, value);
+                block_cache.invalidate(idx);
+            }
+            None => self.memory.write(idx, value),
+        }
+    }
+
+    // Whether anything's attached that only the interpreter keeps up: a trace, profile, history or
+    // recording, or hooks. Anything else that runs programs (like translated code) has to hand
+    // them to run() when this is true.
+    pub fn needs_interpreter(&self) -> bool {
+        self.trace.is_some()
+            || self.profile.is_some()
+            || self.history.is_some()
+            || self.hooks.is_some()
+            || self.recording.is_some()
+    }
+
+    // Run until the program halts or needs input that hasn't been queued yet
+    pub fn run(&mut self) -> Result<HaltReason, IntcodeError> {
+        self.execute(Limits::default(), false)
+    }
+
+    // Like run(), but also pause right after each value is written to output
+    pub fn run_until_output(&mut self) -> Result<HaltReason, IntcodeError> {
+        self.execute(Limits::default(), true)
+    }
+
+    // Like run(), but pause with StepLimitReached after at most this many instructions
+    pub fn run_for(&mut self, steps: usize) -> Result<HaltReason, IntcodeError> {
+        self.run_with_limits(Limits {
+            steps: Some(steps),
+            time: None,
+        })
+    }
+
+    // Like run(), but pause with StepLimitReached or TimeLimitReached once either limit is hit
+    pub fn run_with_limits(&mut self, limits: Limits) -> Result<HaltReason, IntcodeError> {
+        self.execute(limits, false)
+    }
+
+    // Execute a single instruction
+    pub fn step(&mut self) -> Result<HaltReason, IntcodeError> {
+        self.execute(
+            Limits {
+                steps: Some(1),
+                time: None,
+            },
+            true,
+        )
+    }
+
+    fn execute(
+        &mut self,
+        limits: Limits,
+        pause_on_output: bool,
+    ) -> Result<HaltReason, IntcodeError> {
+        let result = if self.block_cache.is_none() && self.hooks.is_none() {
+            self.execute_instructions(&mut Interpreter, limits, pause_on_output)
+        } else {
+            self.execute_wrapped(limits, pause_on_output)
+        };
+        self.halt_reason = result.as_ref().ok().cloned();
+        self.fault = result.as_ref().err().cloned();
+        result
+    }
+
+    // Run on the block cache or hooks, which are taken out while they're used and put around the
+    // engine. Moving them in and out costs more than a short run does, so plain runs skip this.
+    fn execute_wrapped(
+        &mut self,
+        limits: Limits,
+        pause_on_output: bool,
+    ) -> Result<HaltReason, IntcodeError> {
+        let mut block_cache = self.block_cache.take();
+        let mut hooks = self.hooks.take();
+        if let Some(block_cache) = &mut block_cache {
+            let custom_generation = self.custom_generation();
+            block_cache.sync(&mut self.memory, custom_generation);
+        }
+        let result = match (&mut block_cache, &mut hooks) {
+            (None, None) => self.execute_instructions(&mut Interpreter, limits, pause_on_output),
+            (Some(block_cache), None) => {
+                self.execute_instructions(block_cache, limits, pause_on_output)
+            }
+            (None, Some(hooks)) => self.execute_instructions(
+                &mut Hooked::new(hooks, &mut Interpreter),
+                limits,
+                pause_on_output,
+            ),
+            (Some(block_cache), Some(hooks)) => self.execute_instructions(
+                &mut Hooked::new(hooks, block_cache),
+                limits,
+                pause_on_output,
+            ),
+        };
+        self.block_cache = block_cache;
+        self.hooks = hooks;
+        result
+    }
+
+    fn execute_instructions<E: Engine<W>>(
+        &mut self,
+        engine: &mut E,
+        limits: Limits,
+        pause_on_output: bool,
+    ) -> Result<HaltReason, IntcodeError> {
+        let deadline = limits.time.map(|time| Instant::now() + time);
+        let mut steps = 0;
+        // Nothing needs to see each instruction as it runs, so the engine can run ahead on its own
+        let unwatched = self.trace.is_none() && self.profile.is_none() && self.history.is_none();
+
+        while self.ip < self.memory.len() {
+            if limits.steps == Some(steps) {
+                return Ok(StepLimitReached);
+            }
+            if let Some(deadline) = deadline {
+                if steps % STEPS_PER_TIME_CHECK == 0 && Instant::now() >= deadline {
+                    return Ok(TimeLimitReached);
+                }
+            }
+
+            if unwatched {
+                // Stopping at the next step limit or time check
+                let mut max_steps = limits.steps.map_or(usize::MAX, |limit| limit - steps);
+                if deadline.is_some() {
+                    max_steps = max_steps.min(STEPS_PER_TIME_CHECK - steps % STEPS_PER_TIME_CHECK);
+                }
+                let ran = engine.run_ahead(self, max_steps);
+                if ran > 0 {
+                    steps += ran;
+                    continue;
+                }
+            }
+
+            let ip = self.ip;
+            let relative_base = self.relative_base;
+            let fetched = engine.fetch(self)?;
+            let Fetched {
+                instruction,
+                operation,
+                ref params,
+                ..
+            } = fetched;
+            let opcode = operation.opcode;
+            // Read before the instruction runs, since it may overwrite its own operands
+            let operands = match self.trace {
+                Some(_) => self.operands_at(ip, operation),
+                None => Default::default(),
+            };
+            let mut undo = match self.history {
+                Some(_) => Some(self.undo_for(operation, params)),
+                None => None,
+            };
+            let mut wrote_output = false;
+
+            let advance_ip_by = match opcode {
+                // ADD: [c] = [a] + [b]
+                1 => {
+                    let value = params[0]
+                        .checked_add(&params[1])
+                        .ok_or_else(|| self.overflow(instruction))?;
+                    self.store(engine, write_address(&params[2]), value);
+                    4
+                }
+
+                // MULTIPLY: [c] = [a] * [b]
+                2 => {
+                    let value = params[0]
+                        .checked_mul(&params[1])
+                        .ok_or_else(|| self.overflow(instruction))?;
+                    self.store(engine, write_address(&params[2]), value);
+                    4
+                }
+
+                // READ: [a] = input
+                3 => match self.input.read() {
+                    // Pause execution
+                    None => return Ok(NeedsInput),
+                    Some(value) => {
+                        if let Some(recording) = &mut self.recording {
+                            recording.record(self.steps, value.clone());
+                        }
+                        // Kept as read, since a write hook may change what reaches memory
+                        if let Some(undo) = &mut undo {
+                            undo.input = Some(value.clone());
+                        }
+                        self.store(engine, write_address(&params[0]), value);
+                        2
+                    }
+                },
+
+                // WRITE: output = [a]
+                4 => {
+                    self.output.write(params[0].clone());
+                    wrote_output = true;
+                    2
+                }
+
+                // JUMP-IF-TRUE: if [a] != 0 then ip = [b]
+                5 => {
+                    if params[0] != W::default() {
+                        self.ip = self.to_address(instruction, &params[1])?;
+                        0
+                    } else {
+                        3
+                    }
+                }
+
+                // JUMP-IF-FALSE: if [a] == 0 then ip = [b]
+                6 => {
+                    if params[0] == W::default() {
+                        self.ip = self.to_address(instruction, &params[1])?;
+                        0
+                    } else {
+                        3
+                    }
+                }
+
+                // LESS-THAN: [c] = [a] < [b] ? 1 : 0
+                7 => {
+                    let value = W::from_isize(if params[0] < params[1] { 1 } else { 0 });
+                    self.store(engine, write_address(&params[2]), value);
+                    4
+                }
+
+                // EQUALS: [c] = [a] == [b] ? 1 : 0
+                8 => {
+                    let value = W::from_isize(if params[0] == params[1] { 1 } else { 0 });
+                    self.store(engine, write_address(&params[2]), value);
+                    4
+                }
+
+                // ADJUST-RELATIVE-BASE: rb += [a]
+                9 => {
+                    self.relative_base = params[0]
+                        .to_isize()
+                        .and_then(|delta| (self.relative_base as isize).checked_add(delta))
+                        .ok_or_else(|| self.overflow(instruction))?
+                        as usize;
+                    2
+                }
+
+                // END
+                99 => {
+                    self.trace_instruction(ip, &fetched, &operands, relative_base);
+                    self.profile_instruction(ip, &fetched);
+                    self.record_undo(undo, false);
+                    self.steps += 1;
+                    return Ok(Halted);
+                }
+
+                // Anything else is a custom opcode, or it wouldn't have been decoded
+                _ => {
+                    self.execute_custom(engine, instruction, operation, params)?;
+                    operation.size()
+                }
+            };
+
+            self.trace_instruction(ip, &fetched, &operands, relative_base);
+            self.profile_instruction(ip, &fetched);
+            self.record_undo(undo, wrote_output);
+            self.ip += advance_ip_by;
+            self.steps += 1;
+            steps += 1;
+
+            if wrote_output && pause_on_output {
+                return Ok(OutputReady);
+            }
+        }
+
+        Ok(Halted)
+    }
+
+    // The instruction's operands as they are in memory, before they're resolved
+    fn operands_at(&self, ip: usize, operation: &'static Operation) -> [W; MAX_PARAMS] {
+        let mut operands: [W; MAX_PARAMS] = Default::default();
+        for pat in operation.params_at {
+            operands[pat.0] = self.read_mem(ip + 1 + pat.0);
+        }
+        operands
+    }
+
+    // Called once the instruction at ip has completed, with its operands as they were fetched and
+    // the relative base it started with
+    fn trace_instruction(
+        &mut self,
+        ip: usize,
+        fetched: &Fetched<W>,
+        fetched_operands: &[W; MAX_PARAMS],
+        relative_base: usize,
+    ) {
+        let mut trace = match self.trace.take() {
+            Some(trace) => trace,
+            None => return,
+        };
+
+        let Fetched {
+            instruction,
+            operation,
+            param_modes,
+            params,
+        } = fetched;
+        trace.record(ip, operation.opcode, |step| {
+            let mut operands = vec![];
+            let mut writes = vec![];
+
+            for (param, pat) in params.iter().zip(operation.params_at) {
+                operands.push(Operand {
+                    mode: param_modes[pat.0],
+                    param_use: pat.1,
+                    value: fetched_operands[pat.0].clone(),
+                });
+                if pat.1 == Write {
+                    let address = write_address(param);
+                    writes.push((address, self.read_mem(address)));
+                }
+            }
+
+            TraceEvent {
+                step,
+                ip,
+                instruction: *instruction,
+                operation,
+                operands,
+                params: params[..operation.params_at.len()].to_vec(),
+                writes,
+                relative_base,
+                new_relative_base: self.relative_base,
+            }
+        });
+
+        self.trace = Some(trace);
+    }
+
+    fn profile_instruction(&mut self, ip: usize, fetched: &Fetched<W>) {
+        if let Some(profile) = &mut self.profile {
+            let n_params = fetched.operation.params_at.len();
+            profile.record(
+                ip,
+                fetched.operation.opcode,
+                &fetched.param_modes[..n_params],
+            );
+        }
+    }
+
+    // What the instruction at ip is about to change, before it changes it
+    fn undo_for(&self, operation: &'static Operation, params: &[W; MAX_PARAMS]) -> Undo<W> {
+        let write = operation
+            .params_at
+            .iter()
+            .find(|pat| pat.1 == Write)
+            .map(|pat| {
+                let address = write_address(&params[pat.0]);
+                (address, self.read_mem(address))
+            });
+
+        Undo {
+            ip: self.ip,
+            relative_base: self.relative_base,
+            write,
+            input: None,
+            output: false,
+        }
+    }
+
+    // Called once the instruction has completed, with what undo_for() saw before it ran
+    fn record_undo(&mut self, undo: Option<Undo<W>>, wrote_output: bool) {
+        if let Some(mut undo) = undo {
+            undo.output = wrote_output;
+            if let Some(history) = &mut self.history {
+                history.push(undo);
+            }
+        }
+    }
+
+    fn overflow(&self, instruction: Instruction) -> IntcodeError {
+        IntcodeError::Overflow {
+            ip: self.ip,
+            instruction,
+        }
+    }
+
+    fn to_address(&self, instruction: Instruction, address: &W) -> Result<usize, IntcodeError> {
+        match address.to_isize() {
+            Some(address) => self.check_address(instruction, address),
+            None => Err(self.overflow(instruction)),
+        }
+    }
+
+    fn check_address(
+        &self,
+        instruction: Instruction,
+        address: isize,
+    ) -> Result<usize, IntcodeError> {
+        if address < 0 {
+            Err(IntcodeError::NegativeAddress {
+                ip: self.ip,
+                instruction,
+                address,
+            })
+        } else {
+            Ok(address as usize)
+        }
+    }
+
+    pub(crate) fn store<E: Engine<W>>(&mut self, engine: &mut E, address: usize, value: W) {
+        engine.write(&mut self.memory, address, value);
+    }
+
+    #[inline(always)]
+    pub(crate) fn get_params(
+        &self,
+        instruction: Instruction,
+        param_modes: &ParameterModes,
+        params_at: &[ParamAt],
+    ) -> Result<[W; MAX_PARAMS], IntcodeError> {
+        let mut params: [W; MAX_PARAMS] = Default::default();
+        for (param, pat) in params.iter_mut().zip(params_at) {
+            *param = self.get_param(instruction, param_modes[pat.0], pat)?;
+        }
+
+        Ok(params)
+    }
+
+    // Write parameters resolve to the address written, which is always a valid one
+    #[inline(always)]
+    fn get_param(
+        &self,
+        instruction: Instruction,
+        mode: ParameterMode,
+        pat: &ParamAt,
+    ) -> Result<W, IntcodeError> {
+        let ip_offset = pat.0 + 1;
+        let ip_plus_offset = self.ip + ip_offset;
+
+        match mode {
+            PositionMode => {
+                let idx = self.read_mem(ip_plus_offset);
+                let addr = self.to_address(instruction, &idx)?;
+                match pat.1 {
+                    Read => Ok(self.read_mem(addr)),
+                    Write => Ok(idx),
+                }
+            }
+            ImmediateMode => match pat.1 {
+                Read => Ok(self.read_mem(ip_plus_offset)),
+                Write => Err(IntcodeError::ImmediateModeWrite {
+                    ip: self.ip,
+                    instruction,
+                }),
+            },
+            RelativeMode => {
+                let idx = self
+                    .read_mem(ip_plus_offset)
+                    .to_isize()
+                    .and_then(|offset| (self.relative_base as isize).checked_add(offset))
+                    .ok_or_else(|| self.overflow(instruction))?;
+                let addr = self.check_address(instruction, idx)?;
+                match pat.1 {
+                    Read => Ok(self.read_mem(addr)),
+                    Write => Ok(W::from_isize(idx)),
+                }
+            }
+        }
+    }
+}
+
+// An instruction as an engine fetched it: decoded, with its params resolved to the values read,
+// or the address written. The modes are handed on to anything that shows how it was encoded.
+pub(crate) struct Fetched<W> {
+    pub(crate) instruction: Instruction,
+    pub(crate) operation: &'static Operation,
+    pub(crate) param_modes: ParameterModes,
+    pub(crate) params: [W; MAX_PARAMS],
+}
+
+// Where execute_instructions() gets each instruction and its params from, and what it writes to
+// memory through. The loop is compiled separately for each engine, so the plain interpreter never
+// checks for a block cache or hooks as it runs.
+pub(crate) trait Engine<W: Word> {
+    fn fetch<I: InputSource<W>, O: OutputSink<W>>(
+        &mut self,
+        program: &Program<I, O, W>,
+    ) -> Result<Fetched<W>, IntcodeError>;
+
+    fn write(&mut self, memory: &mut PagedMemory<W>, address: usize, value: W);
+
+    // Run up to max_steps instructions straight through, without handing each one back to the
+    // loop, and return how many ran. The engine stops short of anything it leaves to the loop,
+    // which is at least input, output, HLT, custom opcodes, and faults.
+    fn run_ahead<I: InputSource<W>, O: OutputSink<W>>(
+        &mut self,
+        _program: &mut Program<I, O, W>,
+        _max_steps: usize,
+    ) -> usize {
+        0
+    }
+}
+
+// Decodes every instruction from scratch
+pub(crate) struct Interpreter;
+
+impl<W: Word> Engine<W> for Interpreter {
+    #[inline(always)]
+    fn fetch<I: InputSource<W>, O: OutputSink<W>>(
+        &mut self,
+        program: &Program<I, O, W>,
+    ) -> Result<Fetched<W>, IntcodeError> {
+        // A word too big for an isize can't be a valid instruction; reading it as -1 sends it to
+        // the unknown opcode arm
+        let instruction = program.read_mem(program.ip).to_isize().unwrap_or(-1);
+        let opcode = Program::parse_opcode(instruction);
+        let operation =
+            match Operation::from_opcode(opcode).or_else(|| program.custom_operation(opcode)) {
+                Some(operation) => operation,
+                None => {
+                    return Err(IntcodeError::UnknownOpcode {
+                        ip: program.ip,
+                        instruction,
+                    })
+                }
+            };
+        let param_modes = Program::parse_param_modes(instruction, operation.params_at.len())
+            .map_err(|mode| IntcodeError::UnknownParameterMode {
+                ip: program.ip,
+                instruction,
+                mode,
+            })?;
+        let params = program.get_params(instruction, &param_modes, operation.params_at)?;
+
+        Ok(Fetched {
+            instruction,
+            operation,
+            param_modes,
+            params,
+        })
+    }
+
+    #[inline(always)]
+    fn write(&mut self, memory: &mut PagedMemory<W>, address: usize, value: W) {
+        memory.write(address, value);
+    }
+}
+
+// get_param only hands back write addresses that fit in a usize
+pub(crate) fn write_address<W: Word>(param: &W) -> usize {
+    param.to_isize().unwrap() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every test runs on both engines: the plain interpreter and the block cache
+    const ENGINES: [bool; 2] = [false, true];
+
+    fn new(cached: bool, memory: Memory, input: Option<Input>) -> Program {
+        let mut program = Program::new(memory, input);
+        if cached {
+            program.block_cache = Some(BlockCache::new());
+        }
+        program
+    }
+
+    // Run on both engines, checking they end up the same, and hand back the interpreter's program
+    fn run(memory: Memory, input: Option<Input>) -> Program {
+        let mut program = new(false, memory.clone(), input.clone());
+        assert_eq!(program.run(), Ok(Halted));
+        let mut cached = new(true, memory, input);
+        assert_eq!(cached.run(), Ok(Halted));
+        cached.block_cache = None;
+        assert_eq!(cached, program);
+        program
+    }
+
+    fn run_err(memory: Memory) -> IntcodeError {
+        let err = new(false, memory.clone(), None).run().unwrap_err();
+        assert_eq!(new(true, memory, None).run().unwrap_err(), err);
+        err
+    }
+
+    #[test]
+    fn test_parse_memory() {
+        assert_eq!(parse_memory("1,0, -3,99\n"), Ok(vec![1, 0, -3, 99]));
+        assert_eq!(
+            parse_memory("1,0,x,99"),
+            Err(ParseMemoryError {
+                address: 2,
+                value: "x".to_string()
+            })
+        );
+        assert_eq!(
+            parse_memory("").unwrap_err().to_string(),
+            "Invalid value at address 0: "
+        );
+    }
+
+    #[test]
+    fn test_parse_instruction() {
+        assert_eq!(Program::parse_opcode(1002), 2);
+        assert_eq!(Program::parse_opcode(99), 99);
+        assert_eq!(Program::parse_opcode(21107), 7);
+        assert_eq!(
+            Program::parse_param_modes(1002, 3),
+            Ok([PositionMode, ImmediateMode, PositionMode]),
+        );
+        assert_eq!(
+            Program::parse_param_modes(21107, 3),
+            Ok([ImmediateMode, ImmediateMode, RelativeMode]),
+        );
+        assert_eq!(
+            Program::parse_param_modes(204, 1),
+            Ok([RelativeMode, PositionMode, PositionMode]),
+        );
+
+        // Only the modes of parameters the instruction actually takes are looked at
+        assert_eq!(Program::parse_param_modes(90004, 1), Ok([PositionMode; 3]));
+        assert_eq!(Program::parse_param_modes(90004, 3), Err(9));
+    }
+
+    #[test]
+    fn test_program_halt_reasons() {
+        // Echo two inputs, then add them together
+        let memory = vec![3, 13, 4, 13, 3, 14, 4, 14, 1, 13, 14, 15, 99, 0, 0, 0];
+
+        for &cached in &ENGINES {
+            let mut program = new(cached, memory.clone(), Some(vec![3]));
+            assert_eq!(program.run(), Ok(NeedsInput));
+            assert_eq!((program.ip, program.output.clone()), (4, vec![3]));
+            program.input.push_back(4);
+            assert_eq!(program.run(), Ok(Halted));
+            assert_eq!(program.halt_reason, Some(Halted));
+            assert_eq!(
+                (program.output.clone(), program.memory[15]),
+                (vec![3, 4], 7)
+            );
+
+            // Halting again is a no-op
+            assert_eq!(program.run(), Ok(Halted));
+
+            let mut program = new(cached, memory.clone(), Some(vec![3, 4]));
+            assert_eq!(program.run_until_output(), Ok(OutputReady));
+            assert_eq!((program.ip, program.output.clone()), (4, vec![3]));
+            assert_eq!(program.run_until_output(), Ok(OutputReady));
+            assert_eq!((program.ip, program.output.clone()), (8, vec![3, 4]));
+            assert_eq!(program.run_until_output(), Ok(Halted));
+
+            let mut program = new(cached, memory.clone(), Some(vec![3, 4]));
+            assert_eq!(program.step(), Ok(StepLimitReached));
+            assert_eq!(program.step(), Ok(OutputReady));
+            assert_eq!(program.ip, 4);
+            assert_eq!(program.halt_reason, Some(OutputReady));
+        }
+    }
+
+    #[test]
+    fn test_program_limits() {
+        for &cached in &ENGINES {
+            // JT #1, #0: loops forever
+            let mut program = new(cached, vec![1105, 1, 0], None);
+            assert_eq!(program.run_for(10), Ok(StepLimitReached));
+            assert_eq!(program.run_for(10), Ok(StepLimitReached));
+            assert_eq!((program.ip, program.steps), (0, 20));
+
+            let limits = Limits {
+                steps: Some(usize::MAX),
+                time: Some(Duration::from_millis(10)),
+            };
+            assert_eq!(program.run_with_limits(limits), Ok(TimeLimitReached));
+            assert_eq!(program.halt_reason, Some(TimeLimitReached));
+            assert!(program.steps > 20);
+
+            // A limit that isn't reached doesn't get in the way
+            let mut program = new(cached, vec![1101, 2, 3, 5, 99, 0], None);
+            assert_eq!(program.run_for(3), Ok(Halted));
+            assert_eq!((program.memory[5], program.steps), (5, 2));
+            let mut program = new(cached, vec![1101, 2, 3, 5, 99, 0], None);
+            assert_eq!(program.run_for(1), Ok(StepLimitReached));
+            assert_eq!(
+                program.run_with_limits(Limits {
+                    time: Some(Duration::from_secs(60)),
+                    ..Default::default()
+                }),
+                Ok(Halted),
+            );
+        }
+    }
+
+    #[test]
+    fn test_program_run_errors() {
+        assert_eq!(
+            run_err(vec![1, 0, 0, 0, 42]),
+            IntcodeError::UnknownOpcode {
+                ip: 4,
+                instruction: 42,
+            },
+        );
+        assert_eq!(
+            run_err(vec![-1101, 0, 0, 0, 99]),
+            IntcodeError::UnknownOpcode {
+                ip: 0,
+                instruction: -1101,
+            },
+        );
+        assert_eq!(
+            run_err(vec![301, 0, 0, 0, 99]),
+            IntcodeError::UnknownParameterMode {
+                ip: 0,
+                instruction: 301,
+                mode: 3,
+            },
+        );
+        assert_eq!(
+            run_err(vec![11101, 1, 1, 0, 99]),
+            IntcodeError::ImmediateModeWrite {
+                ip: 0,
+                instruction: 11101,
+            },
+        );
+        assert_eq!(
+            run_err(vec![1, -1, 0, 0, 99]),
+            IntcodeError::NegativeAddress {
+                ip: 0,
+                instruction: 1,
+                address: -1,
+            },
+        );
+        assert_eq!(
+            run_err(vec![1105, 1, -7, 99]),
+            IntcodeError::NegativeAddress {
+                ip: 0,
+                instruction: 1105,
+                address: -7,
+            },
+        );
+        assert_eq!(
+            run_err(vec![109, -5, 204, 1, 99]),
+            IntcodeError::NegativeAddress {
+                ip: 2,
+                instruction: 204,
+                address: -4,
+            },
+        );
+
+        assert_eq!(
+            run_err(vec![1102, isize::MAX, 2, 0, 99]),
+            IntcodeError::Overflow {
+                ip: 0,
+                instruction: 1102,
+            },
+        );
+        assert_eq!(
+            run_err(vec![109, 1, 204, isize::MAX, 99]),
+            IntcodeError::Overflow {
+                ip: 2,
+                instruction: 204,
+            },
+        );
+
+        // The program is left at the faulting instruction, with everything before it applied
+        for &cached in &ENGINES {
+            let mut program = new(cached, vec![3, 0, 104, 7, 2, 0, 0, 0, 12], Some(vec![8]));
+            let err = program.run().unwrap_err();
+            assert_eq!((err.ip(), err.instruction()), (8, 12));
+            assert_eq!(program.ip, 8);
+            assert_eq!(program.memory, vec![64, 0, 104, 7, 2, 0, 0, 0, 12]);
+            assert_eq!(program.output, vec![7]);
+            assert_eq!(program.halt_reason, None);
+            assert_eq!(program.fault, Some(err.clone()));
+            assert_eq!(
+                err.to_string(),
+                "Unknown opcode (ip=8, instruction=12)".to_string(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_program_run_day_09() {
+        assert_eq!(
+            run(vec![109, 10, 109, -2, 204, -7, 99], None).output,
+            vec![10],
+        );
+        assert_eq!(
+            run(vec![109, 8, 203, 10, 99], Some(vec![11])),
+            Program {
+                memory: {
+                    let mut memory = PagedMemory::from(vec![109, 8, 203, 10, 99]);
+                    memory.write(18, 11);
+                    memory
+                },
+                ip: 4,
+                relative_base: 8,
+                steps: 3,
+                input: VecDeque::new(),
+                output: vec![],
+                halt_reason: Some(Halted),
+                fault: None,
+                trace: None,
+                profile: None,
+                history: None,
+                block_cache: None,
+                hooks: None,
+                custom_opcodes: None,
+                recording: None,
+            },
+        );
+        assert_eq!(
+            run(
+                vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99],
+                None
+            )
+            .output,
+            vec![109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99],
+        );
+        assert_eq!(
+            run(vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0], None).output,
+            vec![1219070632396864],
+        );
+        assert_eq!(
+            run(vec![104, 1125899906842624, 99], None).output,
+            vec![1125899906842624],
+        );
+
+        // Reading far past the end of the program gives 0, without allocating any memory
+        let program = run(vec![109, 1000000, 204, 0, 99], None);
+        assert_eq!(program.output, vec![0]);
+        assert_eq!(program.memory.stats().pages, 1);
+    }
+
+    #[test]
+    fn test_program_run_day_07() {
+        assert_eq!(
+            run(vec![1002, 4, 3, 4, 33], None).memory,
+            vec![1002, 4, 3, 4, 99],
+        );
+        assert_eq!(
+            run(vec![3, 0, 4, 0, 99], Some(vec![1234])),
+            Program {
+                memory: vec![1234, 0, 4, 0, 99].into(),
+                ip: 4,
+                relative_base: 0,
+                steps: 3,
+                input: VecDeque::new(),
+                output: vec![1234],
+                halt_reason: Some(Halted),
+                fault: None,
+                trace: None,
+                profile: None,
+                history: None,
+                block_cache: None,
+                hooks: None,
+                custom_opcodes: None,
+                recording: None,
+            },
+        );
+    }
+
+    #[test]
+    fn test_program_run_day_05() {
+        // Output 1 if the input is equal to (or less than) 8, using position and immediate mode
+        let compare_programs = [
+            (vec![3, 9, 8, 9, 10, 9, 4, 9, 99, -1, 8], [0, 1, 0]),
+            (vec![3, 9, 7, 9, 10, 9, 4, 9, 99, -1, 8], [1, 0, 0]),
+            (vec![3, 3, 1108, -1, 8, 3, 4, 3, 99], [0, 1, 0]),
+            (vec![3, 3, 1107, -1, 8, 3, 4, 3, 99], [1, 0, 0]),
+        ];
+        for (memory, expected) in compare_programs {
+            for (input, output) in [7, 8, 9].iter().zip(expected.iter()) {
+                assert_eq!(
+                    run(memory.clone(), Some(vec![*input])).output,
+                    vec![*output],
+                );
+            }
+        }
+
+        // Output 0 if the input was zero or 1 if the input was non-zero, using jumps
+        for memory in &[
+            vec![3, 12, 6, 12, 15, 1, 13, 14, 13, 4, 13, 99, -1, 0, 1, 9],
+            vec![3, 3, 1105, -1, 9, 1101, 0, 0, 12, 4, 12, 99, 1],
+        ] {
+            assert_eq!(run(memory.clone(), Some(vec![0])).output, vec![0]);
+            assert_eq!(run(memory.clone(), Some(vec![5])).output, vec![1]);
+        }
+    }
+
+    #[test]
+    fn test_program_run_day_02() {
+        assert_eq!(
+            run(vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50], None).memory,
+            vec![3500, 9, 10, 70, 2, 3, 11, 0, 99, 30, 40, 50],
+        );
+        assert_eq!(run(vec![1, 0, 0, 0, 99], None).memory, vec![2, 0, 0, 0, 99]);
+        assert_eq!(run(vec![2, 3, 0, 3, 99], None).memory, vec![2, 3, 0, 6, 99]);
+        assert_eq!(
+            run(vec![2, 4, 4, 5, 99, 0], None).memory,
+            vec![2, 4, 4, 5, 99, 9801],
+        );
+        assert_eq!(
+            run(vec![1, 1, 1, 4, 99, 5, 6, 0, 99], None).memory,
+            vec![30, 1, 1, 4, 2, 5, 6, 0, 99],
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::device::OutputSink;
+use crate::error::{RecordingError, ReplayError};
+use crate::program::{HaltReason, Program};
+use crate::word::Word;
+use HaltReason::*;
+
+// Bump this whenever the on-disk format changes
+pub const RECORDING_VERSION: u32 = 1;
+
+const HEADER: &str = "intcode recording v";
+
+// Every value a program has read, with how many instructions it had executed when it read it. Set
+// Program::recording to start recording, and play the session back through a fresh program with
+// Program::replay. Stepping back through a program's history takes the values it read back out.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct Recording<W = isize> {
+    pub inputs: Vec<RecordedInput<W>>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RecordedInput<W = isize> {
+    // Program::steps when the IN instruction that read it ran
+    pub step: usize,
+    pub value: W,
+}
+
+impl<W> Recording<W> {
+    pub fn new() -> Recording<W> {
+        Recording { inputs: vec![] }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    pub(crate) fn record(&mut self, step: usize, value: W) {
+        self.inputs.push(RecordedInput { step, value });
+    }
+
+    pub(crate) fn unrecord(&mut self) {
+        self.inputs.pop();
+    }
+}
+
+impl<O: OutputSink<W>, W: Word> Program<VecDeque<W>, O, W> {
+    // Run a program from where a recording started, giving it each recorded value just as it
+    // executes its recorded step, and then on until it halts or needs more input. Fails with
+    // Diverged if the program doesn't read a value at the step it was recorded at, which means it
+    // isn't the program, or the starting state, the recording was made from.
+    pub fn replay(&mut self, recording: &Recording<W>) -> Result<HaltReason, ReplayError> {
+        for recorded in &recording.inputs {
+            let diverged = ReplayError::Diverged {
+                step: recorded.step,
+            };
+
+            // Anything already queued would be read in place of the recorded values
+            if !self.input.is_empty() || recorded.step < self.steps {
+                return Err(diverged);
+            }
+            if self.run_for(recorded.step - self.steps)? != StepLimitReached {
+                return Err(diverged);
+            }
+
+            self.input.push_back(recorded.value.clone());
+            self.step()?;
+            if !self.input.is_empty() {
+                return Err(diverged);
+            }
+        }
+
+        Ok(self.run()?)
+    }
+}
+
+// One line per value read, step=value, e.g.
+//   intcode recording v1
+//   12=1
+//   240=-1
+impl<W: Display> Display for Recording<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}{}", HEADER, RECORDING_VERSION)?;
+        for recorded in &self.inputs {
+            writeln!(f, "{}={}", recorded.step, recorded.value)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Recording {
+    type Err = RecordingError;
+
+    fn from_str(s: &str) -> Result<Recording, RecordingError> {
+        let mut lines = s.lines();
+
+        let header = lines.next().unwrap_or("").trim();
+        let version = match header.strip_prefix(HEADER).map(str::parse) {
+            Some(Ok(version)) => version,
+            _ => {
+                return Err(RecordingError::InvalidHeader {
+                    header: header.to_string(),
+                })
+            }
+        };
+        if version != RECORDING_VERSION {
+            return Err(RecordingError::UnsupportedVersion { version });
+        }
+
+        let mut recording = Recording::new();
+        for (idx, line) in lines.enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            match (
+                parts.next().and_then(|s| s.trim().parse().ok()),
+                parts.next().and_then(|v| v.trim().parse().ok()),
+            ) {
+                (Some(step), Some(value)) => recording.record(step, value),
+                _ => {
+                    return Err(RecordingError::InvalidLine {
+                        line: idx + 2,
+                        text: line.to_string(),
+                    })
+                }
+            }
+        }
+
+        Ok(recording)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::history::History;
+
+    // Add up the inputs until a 0, outputting the total so far after each one; negative inputs
+    // are skipped without being added
+    const ADDER: &str = "
+        loop:   IN n
+                JF n, #done
+                LT n, #0, skip
+                JT skip, #loop
+                ADD n, total, total
+                OUT total
+                JT #1, #loop
+        done:   HLT
+        n:      .data 0
+        total:  .data 0
+        skip:   .data 0
+    ";
+
+    #[test]
+    fn test_recording() {
+        let memory = assemble(ADDER).unwrap();
+        let mut program = Program::new(memory.clone(), Some(vec![4, -1]));
+        program.recording = Some(Recording::new());
+        assert_eq!(program.run(), Ok(NeedsInput));
+        program.input.push_back(6);
+        program.input.push_back(0);
+        assert_eq!(program.run(), Ok(Halted));
+
+        let recording = program.recording.take().unwrap();
+        assert_eq!(
+            recording.to_string(),
+            "intcode recording v1\n0=4\n7=-1\n11=6\n18=0\n"
+        );
+        assert_eq!(recording.to_string().parse(), Ok(recording.clone()));
+
+        let mut replayed = Program::new(memory.clone(), None);
+        assert_eq!(replayed.replay(&recording), Ok(Halted));
+        assert_eq!(replayed.output, program.output);
+        assert_eq!(replayed.steps, program.steps);
+
+        // The same values read at other steps aren't the same session
+        let mut other = Program::new(vec![3, 9, 3, 9, 3, 9, 3, 9, 99, 0], None);
+        assert_eq!(
+            other.replay(&recording),
+            Err(ReplayError::Diverged { step: 7 })
+        );
+
+        // And neither is a session that starts with input already queued
+        let mut queued = Program::new(memory, Some(vec![4]));
+        assert_eq!(
+            queued.replay(&recording),
+            Err(ReplayError::Diverged { step: 0 })
+        );
+    }
+
+    #[test]
+    fn test_recording_step_back() {
+        let memory = assemble(ADDER).unwrap();
+        let mut program = Program::new(memory, Some(vec![1, 2]));
+        program.recording = Some(Recording::new());
+        program.history = Some(History::new(100));
+        assert_eq!(program.run(), Ok(NeedsInput));
+
+        // Undoing the read of 2 forgets it was read
+        program.step_back(7);
+        assert_eq!(program.recording.as_ref().unwrap().len(), 1);
+        program.input.clear();
+        program.input.push_back(5);
+        program.input.push_back(0);
+        assert_eq!(program.run(), Ok(Halted));
+        assert_eq!(
+            program.recording.unwrap().to_string(),
+            "intcode recording v1\n0=1\n7=5\n14=0\n"
+        );
+    }
+
+    #[test]
+    fn test_recording_across_snapshot() {
+        let memory = assemble(ADDER).unwrap();
+        let mut program = Program::new(memory.clone(), Some(vec![1, 2]));
+        program.recording = Some(Recording::new());
+        assert_eq!(program.run(), Ok(NeedsInput));
+
+        // Picked back up from a snapshot, the session carries on being recorded at the right steps
+        let mut resumed = Program::from_snapshot(&program.snapshot());
+        resumed.recording = program.recording.take();
+        resumed.input.push_back(0);
+        assert_eq!(resumed.run(), Ok(Halted));
+
+        let mut replayed = Program::new(memory, None);
+        assert_eq!(
+            replayed.replay(resumed.recording.as_ref().unwrap()),
+            Ok(Halted)
+        );
+        assert_eq!(replayed.output, resumed.output);
+    }
+
+    #[test]
+    fn test_recording_text_errors() {
+        use RecordingError::*;
+
+        let parse = |s: &str| s.parse::<Recording>().unwrap_err();
+        assert_eq!(
+            parse("0=1"),
+            InvalidHeader {
+                header: "0=1".to_string()
+            },
+        );
+        assert_eq!(
+            parse("intcode recording v2\n0=1"),
+            UnsupportedVersion { version: 2 },
+        );
+        assert_eq!(
+            parse("intcode recording v1\n0=1\n\n5"),
+            InvalidLine {
+                line: 4,
+                text: "5".to_string()
+            },
+        );
+        assert_eq!(
+            parse("intcode recording v1\nx=1").to_string(),
+            "Invalid input on line 2: x=1",
+        );
+    }
+}
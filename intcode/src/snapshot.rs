@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::error::SnapshotError;
+use crate::memory::PagedMemory;
+use crate::program::{InputQueue, Output, Program};
+
+// Bump this whenever the on-disk format changes. Version 1 snapshots, which came before steps was
+// saved, are still read, as having taken no steps.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+const HEADER: &str = "intcode snapshot v";
+const FIELDS: [&str; 7] = [
+    "ip",
+    "steps",
+    "relative_base",
+    "memory",
+    "ext_memory",
+    "input",
+    "output",
+];
+
+// Everything needed to pick a program back up where it left off. Take one with Program::snapshot
+// and branch from it with Program::from_snapshot or Program::restore, as many times as needed.
+// Branches share memory pages with the snapshot, and only copy the ones they write to.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Snapshot {
+    pub memory: PagedMemory,
+    pub ip: usize,
+    pub relative_base: usize,
+    pub steps: usize,
+    pub input: InputQueue,
+    pub output: Output,
+}
+
+impl Program {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.clone(),
+            ip: self.ip,
+            relative_base: self.relative_base,
+            steps: self.steps,
+            input: self.input.clone(),
+            output: self.output.clone(),
+        }
+    }
+
+    pub fn from_snapshot(snapshot: &Snapshot) -> Program {
+        let mut program = Program::new(vec![], None);
+        program.restore(snapshot);
+        program
+    }
+
+    // steps goes back to what it was, so step budgets and recordings carry on from there, while any
+    // trace or profile keeps recording. halt_reason and fault are cleared, since this state hasn't
+    // been run yet, and so is any history, which no longer leads here.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.memory = snapshot.memory.clone();
+        self.ip = snapshot.ip;
+        self.relative_base = snapshot.relative_base;
+        self.steps = snapshot.steps;
+        self.input = snapshot.input.clone();
+        self.output = snapshot.output.clone();
+        self.halt_reason = None;
+        self.fault = None;
+        if let Some(history) = &mut self.history {
+            history.clear();
+        }
+    }
+}
+
+fn write_field<T: ToString>(
+    f: &mut Formatter<'_>,
+    name: &str,
+    values: impl Iterator<Item = T>,
+) -> fmt::Result {
+    let values: Vec<String> = values.map(|value| value.to_string()).collect();
+    if values.is_empty() {
+        writeln!(f, "{}:", name)
+    } else {
+        writeln!(f, "{}: {}", name, values.join(","))
+    }
+}
+
+// A line-based text format, e.g.
+//   intcode snapshot v2
+//   ip: 4
+//   steps: 2
+//   relative_base: 8
+//   memory: 109,8,203,10,99
+//   ext_memory: 18=11
+//   input:
+//   output: 3,4
+// memory is the loaded image, and ext_memory is every cell past it that isn't 0, in address order.
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let ext_memory = self
+            .memory
+            .nonzero_cells()
+            .into_iter()
+            .filter(|(address, _)| *address >= self.memory.len());
+
+        writeln!(f, "{}{}", HEADER, SNAPSHOT_VERSION)?;
+        writeln!(f, "ip: {}", self.ip)?;
+        writeln!(f, "steps: {}", self.steps)?;
+        writeln!(f, "relative_base: {}", self.relative_base)?;
+        write_field(f, "memory", self.memory.to_vec().iter())?;
+        write_field(
+            f,
+            "ext_memory",
+            ext_memory.map(|(address, value)| format!("{}={}", address, value)),
+        )?;
+        write_field(f, "input", self.input.iter())?;
+        write_field(f, "output", self.output.iter())
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = SnapshotError;
+
+    fn from_str(s: &str) -> Result<Snapshot, SnapshotError> {
+        let mut lines = s.lines();
+
+        let header = lines.next().unwrap_or("").trim();
+        let version = match header.strip_prefix(HEADER).map(str::parse) {
+            Some(Ok(version)) => version,
+            _ => {
+                return Err(SnapshotError::InvalidHeader {
+                    header: header.to_string(),
+                })
+            }
+        };
+        if version != 1 && version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion { version });
+        }
+
+        let mut fields = HashMap::new();
+        for line in lines.map(str::trim).filter(|line| !line.is_empty()) {
+            let (field, value) = match line.find(':') {
+                Some(colon_idx) => (&line[..colon_idx], line[(colon_idx + 1)..].trim()),
+                None => (line, ""),
+            };
+            if !FIELDS.contains(&field) || (version == 1 && field == "steps") {
+                return Err(SnapshotError::UnknownField {
+                    field: field.to_string(),
+                });
+            }
+            if fields.insert(field, value).is_some() {
+                return Err(SnapshotError::DuplicateField {
+                    field: field.to_string(),
+                });
+            }
+        }
+
+        let field = |field: &'static str| -> Result<&str, SnapshotError> {
+            fields
+                .get(field)
+                .copied()
+                .ok_or(SnapshotError::MissingField { field })
+        };
+        let invalid_value = |field: &'static str, value: &str| SnapshotError::InvalidValue {
+            field,
+            value: value.to_string(),
+        };
+        let number = |name: &'static str| -> Result<usize, SnapshotError> {
+            let value = field(name)?;
+            value.parse().map_err(|_| invalid_value(name, value))
+        };
+        let list = |name: &'static str| -> Result<Vec<isize>, SnapshotError> {
+            let value = field(name)?;
+            if value.is_empty() {
+                return Ok(vec![]);
+            }
+            value
+                .split(',')
+                .map(|v| v.trim().parse().map_err(|_| invalid_value(name, v)))
+                .collect()
+        };
+
+        let mut memory = PagedMemory::from(list("memory")?);
+        let value = field("ext_memory")?;
+        for cell in value.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+            let mut parts = cell.splitn(2, '=');
+            match (
+                parts.next().and_then(|a| a.trim().parse().ok()),
+                parts.next().and_then(|v| v.trim().parse().ok()),
+            ) {
+                (Some(address), Some(value)) if address >= memory.len() => {
+                    memory.write(address, value)
+                }
+                _ => return Err(invalid_value("ext_memory", cell)),
+            };
+        }
+
+        Ok(Snapshot {
+            memory,
+            ip: number("ip")?,
+            relative_base: number("relative_base")?,
+            steps: match version {
+                1 => 0,
+                _ => number("steps")?,
+            },
+            input: list("input")?.into(),
+            output: list("output")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::HaltReason::*;
+
+    #[test]
+    fn test_snapshot_branch() {
+        // Echo two inputs, then add them together
+        let memory = vec![3, 13, 4, 13, 3, 14, 4, 14, 1, 13, 14, 15, 99, 0, 0, 0];
+        let mut program = Program::new(memory, Some(vec![3]));
+        assert_eq!(program.run(), Ok(NeedsInput));
+        let snapshot = program.snapshot();
+        assert_eq!(snapshot.steps, 2);
+
+        // Every branch starts from the same state, no matter what the others did
+        for second in 1..4 {
+            let mut branch = Program::from_snapshot(&snapshot);
+            branch.input.push_back(second);
+            assert_eq!(branch.run(), Ok(Halted));
+            assert_eq!(branch.output, vec![3, second]);
+            assert_eq!(branch.memory[15], 3 + second);
+            assert_eq!(branch.steps, 6);
+        }
+
+        program.input.push_back(10);
+        assert_eq!(program.run(), Ok(Halted));
+        program.restore(&snapshot);
+        assert_eq!(program.snapshot(), snapshot);
+        assert_eq!(program.halt_reason, None);
+        assert_eq!(program.ip, 4);
+        assert_eq!(program.steps, 2);
+    }
+
+    #[test]
+    fn test_snapshot_text() {
+        let mut program = Program::new(vec![109, 8, 203, 10, 203, 30, 99], Some(vec![11, 12, 13]));
+        assert_eq!(program.run(), Ok(Halted));
+        let snapshot = program.snapshot();
+
+        assert_eq!(
+            snapshot.to_string(),
+            [
+                "intcode snapshot v2",
+                "ip: 6",
+                "steps: 4",
+                "relative_base: 8",
+                "memory: 109,8,203,10,203,30,99",
+                "ext_memory: 18=11,38=12",
+                "input: 13",
+                "output:",
+                "",
+            ]
+            .join("\n"),
+        );
+        assert_eq!(snapshot.to_string().parse(), Ok(snapshot));
+
+        // Fields can come in any order, and blank lines are skipped
+        assert_eq!(
+            "intcode snapshot v2\noutput: 1, 2\n\nip: 0\nrelative_base: 0\nmemory: 99\next_memory:\ninput:\nsteps: 7"
+                .parse(),
+            Ok(Snapshot {
+                memory: vec![99].into(),
+                ip: 0,
+                relative_base: 0,
+                steps: 7,
+                input: InputQueue::new(),
+                output: vec![1, 2],
+            }),
+        );
+
+        // Version 1 snapshots didn't save steps
+        assert_eq!(
+            "intcode snapshot v1\nip: 0\nrelative_base: 0\nmemory: 99\next_memory:\ninput:\noutput:"
+                .parse::<Snapshot>()
+                .map(|snapshot| snapshot.steps),
+            Ok(0),
+        );
+    }
+
+    #[test]
+    fn test_snapshot_text_errors() {
+        use SnapshotError::*;
+
+        let parse = |s: &str| s.parse::<Snapshot>().unwrap_err();
+        let valid_v1 = "ip: 0\nrelative_base: 0\nmemory: 99\next_memory:\ninput:\noutput:";
+        let valid = format!("{}\nsteps: 0", valid_v1);
+
+        assert_eq!(
+            parse("1,2,3"),
+            InvalidHeader {
+                header: "1,2,3".to_string()
+            },
+        );
+        assert_eq!(
+            parse(&format!("intcode snapshot v3\n{}", valid)),
+            UnsupportedVersion { version: 3 },
+        );
+        assert_eq!(
+            parse("intcode snapshot v2\nip: 0\nsteps: 0\nmemory: 99\next_memory:\ninput:\noutput:"),
+            MissingField {
+                field: "relative_base"
+            },
+        );
+        assert_eq!(
+            parse(&format!("intcode snapshot v2\n{}", valid_v1)),
+            MissingField { field: "steps" },
+        );
+        assert_eq!(
+            parse(&format!("intcode snapshot v2\n{}\nip: 1", valid)),
+            DuplicateField {
+                field: "ip".to_string()
+            },
+        );
+        assert_eq!(
+            parse(&format!("intcode snapshot v2\n{}\nhalt_reason: 1", valid)),
+            UnknownField {
+                field: "halt_reason".to_string()
+            },
+        );
+        assert_eq!(
+            parse(&format!("intcode snapshot v1\n{}", valid)),
+            UnknownField {
+                field: "steps".to_string()
+            },
+        );
+        assert_eq!(
+            parse(&format!(
+                "intcode snapshot v2\n{}",
+                valid.replace("99", "99,x")
+            )),
+            InvalidValue {
+                field: "memory",
+                value: "x".to_string()
+            },
+        );
+        assert_eq!(
+            parse(&format!(
+                "intcode snapshot v2\n{}",
+                valid.replace("ext_memory:", "ext_memory: 5")
+            )),
+            InvalidValue {
+                field: "ext_memory",
+                value: "5".to_string()
+            },
+        );
+        // Cells inside the image belong in memory, not ext_memory
+        assert_eq!(
+            parse(&format!(
+                "intcode snapshot v2\n{}",
+                valid.replace("ext_memory:", "ext_memory: 0=5")
+            )),
+            InvalidValue {
+                field: "ext_memory",
+                value: "0=5".to_string()
+            },
+        );
+        assert_eq!(
+            parse("intcode snapshot v3").to_string(),
+            "Unsupported snapshot version: 3 (expected 2)",
+        );
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::VecDeque;
+use std::panic;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::device::{InputSource, OutputSink};
+use crate::error::{IntcodeError, ThreadedError};
+use crate::network::{Network, NetworkStop, Node, Routing};
+use crate::program::{HaltReason, Program};
+use crate::word::Word;
+
+// How long a program waiting for input blocks before checking whether every other one is waiting
+// too
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(1);
+
+// What the threads know about each other, to tell when none of them can go on
+#[derive(Debug, Default)]
+struct Traffic {
+    // Programs still running
+    running: usize,
+    // How many of those are blocked reading input
+    waiting: usize,
+    // Values sent from one program to another that haven't been read yet
+    in_flight: usize,
+    idle: bool,
+}
+
+type SharedTraffic = Arc<Mutex<Traffic>>;
+
+// A program's input on its own thread: blocks until the program before it sends something, and
+// gives None once that program has finished, or every program is waiting
+struct ChannelInput<W> {
+    receiver: Receiver<W>,
+    traffic: SharedTraffic,
+}
+
+impl<W> InputSource<W> for ChannelInput<W> {
+    fn read(&mut self) -> Option<W> {
+        let mut traffic = self.traffic.lock().unwrap();
+        traffic.waiting += 1;
+        let value = loop {
+            match self.receiver.try_recv() {
+                Ok(value) => break Some(value),
+                Err(TryRecvError::Disconnected) => break None,
+                Err(TryRecvError::Empty) => {}
+            }
+            if traffic.idle || (traffic.waiting == traffic.running && traffic.in_flight == 0) {
+                traffic.idle = true;
+                break None;
+            }
+
+            drop(traffic);
+            let received = self.receiver.recv_timeout(IDLE_CHECK_INTERVAL);
+            traffic = self.traffic.lock().unwrap();
+            match received {
+                Ok(value) => break Some(value),
+                Err(RecvTimeoutError::Disconnected) => break None,
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+        };
+        traffic.waiting -= 1;
+        if value.is_some() {
+            traffic.in_flight -= 1;
+        }
+
+        value
+    }
+
+    fn unread(&mut self, _value: W) -> bool {
+        false
+    }
+}
+
+// A program's output on its own thread: sent on to the next program, or for the last one in a
+// pipeline, kept to be handed back
+enum ChannelOutput<W> {
+    Next {
+        sender: Sender<W>,
+        traffic: SharedTraffic,
+    },
+    Kept(Vec<W>),
+}
+
+impl<W> OutputSink<W> for ChannelOutput<W> {
+    fn write(&mut self, value: W) {
+        match self {
+            ChannelOutput::Next { sender, traffic } => {
+                let mut traffic = traffic.lock().unwrap();
+                traffic.in_flight += 1;
+                sender.send(value).ok();
+            }
+            ChannelOutput::Kept(output) => output.push(value),
+        }
+    }
+
+    fn unwrite(&mut self) -> bool {
+        match self {
+            ChannelOutput::Next { .. } => false,
+            ChannelOutput::Kept(output) => output.pop().is_some(),
+        }
+    }
+}
+
+type Threaded<W> = Program<ChannelInput<W>, ChannelOutput<W>, W>;
+
+impl<W: Word + Send + Sync + 'static> Network<W> {
+    // Like run(), but with every program running at once on a thread of its own, connected to the
+    // others by channels. A program that reads input blocks until some is sent, so turn_limits
+    // and idle_input don't apply. Once a program finishes, the one after it gives up waiting for
+    // input from it, so the network shuts down as soon as it has no more to do. A network that
+    // routes packets can't be run this way, and is left as it was.
+    pub fn run_threaded(&mut self) -> Result<NetworkStop, ThreadedError> {
+        if self.routing == Routing::Packets {
+            return Err(ThreadedError::PacketRouting);
+        }
+
+        // Anything already output is sent on first, as run() would
+        for idx in 0..self.programs.len() {
+            self.route(idx);
+        }
+
+        let traffic = Arc::new(Mutex::new(Traffic::default()));
+        let (senders, receivers): (Vec<Sender<W>>, Vec<Receiver<W>>) =
+            self.programs.iter().map(|_| channel()).unzip();
+        let mut programs = vec![];
+        for (idx, (program, receiver)) in self.programs.drain(..).zip(receivers).enumerate() {
+            let output = match (self.routing, senders.get(idx + 1)) {
+                (Routing::Pipeline, None) => ChannelOutput::Kept(vec![]),
+                (_, sender) => ChannelOutput::Next {
+                    sender: sender.unwrap_or(&senders[0]).clone(),
+                    traffic: traffic.clone(),
+                },
+            };
+            let input = ChannelInput {
+                receiver,
+                traffic: traffic.clone(),
+            };
+            let (program, queued, kept) = program.replace_devices(input, output);
+
+            let mut traffic = traffic.lock().unwrap();
+            traffic.in_flight += queued.len();
+            if program.halt_reason != Some(HaltReason::Halted) {
+                traffic.running += 1;
+            }
+            for value in queued {
+                senders[idx].send(value).ok();
+            }
+            programs.push((program, kept));
+        }
+        // Each program's only sender now belongs to the program before it
+        drop(senders);
+
+        let threads: Vec<_> = programs
+            .into_iter()
+            .map(|(program, kept)| {
+                let traffic = traffic.clone();
+                thread::spawn(move || run_on_thread(program, kept, &traffic))
+            })
+            .collect();
+
+        let mut result = Ok(NetworkStop::Halted);
+        let mut receivers = vec![];
+        for thread in threads {
+            let (program, receiver, program_result) = match thread.join() {
+                Ok(joined) => joined,
+                Err(panic) => panic::resume_unwind(panic),
+            };
+            match program_result {
+                Ok(HaltReason::Halted) => {}
+                Ok(_) if result.is_ok() => result = Ok(NetworkStop::Idle),
+                Err(err) if result.is_ok() => result = Err(err.into()),
+                _ => {}
+            }
+            self.programs.push(program);
+            receivers.push(receiver);
+        }
+
+        // Whatever was sent and never read goes back to being queued, including anything sent to
+        // a program after it finished
+        for (program, receiver) in self.programs.iter_mut().zip(receivers) {
+            program.input.extend(receiver.try_iter());
+        }
+
+        result
+    }
+}
+
+// Run the program, then hand it back on its old devices, along with what it was reading from so
+// nothing sent to it is lost
+fn run_on_thread<W: Word>(
+    mut program: Threaded<W>,
+    kept: Vec<W>,
+    traffic: &SharedTraffic,
+) -> (Node<W>, Receiver<W>, Result<HaltReason, IntcodeError>) {
+    let result = match program.halt_reason {
+        Some(HaltReason::Halted) => Ok(HaltReason::Halted),
+        _ => {
+            let result = program.run();
+            traffic.lock().unwrap().running -= 1;
+            result
+        }
+    };
+
+    // Dropping the sender lets the next program know nothing more is coming
+    let (mut program, input, output) = program.replace_devices(VecDeque::new(), kept);
+    if let ChannelOutput::Kept(output) = output {
+        program.output.extend(output);
+    }
+
+    (program, input.receiver, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use itertools::Itertools;
+
+    // The day 7 feedback loop example
+    const AMPLIFIER: [isize; 29] = [
+        3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28,
+        1005, 28, 6, 99, 0, 0, 5,
+    ];
+
+    fn amplifiers(phase_settings: &[isize], routing: Routing) -> Network {
+        let amplifiers = phase_settings
+            .iter()
+            .map(|&phase| Program::new(AMPLIFIER.to_vec(), Some(vec![phase])))
+            .collect();
+        let mut network = Network::new(amplifiers, routing);
+        network.programs[0].input.push_back(0);
+        network
+    }
+
+    #[test]
+    fn test_run_threaded() {
+        // Every ordering at once, each on threads of its own, has to agree with taking turns
+        let threads: Vec<_> = (5..=9)
+            .permutations(5)
+            .map(|phase_settings| {
+                thread::spawn(move || {
+                    let mut network = amplifiers(&phase_settings, Routing::Ring);
+                    assert_eq!(network.run_threaded(), Ok(NetworkStop::Halted));
+                    (phase_settings, network)
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            let (phase_settings, threaded) = thread.join().unwrap();
+            let mut network = amplifiers(&phase_settings, Routing::Ring);
+            assert_eq!(network.run(), Ok(NetworkStop::Halted));
+            for (threaded, program) in threaded.programs.iter().zip(&network.programs) {
+                assert_eq!(threaded.memory, program.memory);
+                assert_eq!(threaded.input, program.input);
+                assert_eq!(threaded.steps, program.steps);
+            }
+        }
+
+        let mut network = amplifiers(&[9, 8, 7, 6, 5], Routing::Ring);
+        network.run_threaded().unwrap();
+        assert_eq!(network.programs[0].input, vec![139629729]);
+    }
+
+    #[test]
+    fn test_run_threaded_idle() {
+        // Lined up, the amplifiers make one pass and then wait on each other for good
+        let mut network = amplifiers(&[9, 8, 7, 6, 5], Routing::Pipeline);
+        assert_eq!(network.run_threaded(), Ok(NetworkStop::Idle));
+        assert_eq!(network.programs[4].output, vec![129]);
+        assert!(network
+            .programs
+            .iter()
+            .all(|program| program.halt_reason == Some(HaltReason::NeedsInput)));
+
+        // A ring where each program waits to hear from the one before it never gets going
+        let memory = assemble("IN 5\nOUT 5\nHLT\n.data 0").unwrap();
+        let programs = vec![
+            Program::new(memory.clone(), None),
+            Program::new(memory, None),
+        ];
+        let mut network = Network::new(programs, Routing::Ring);
+        assert_eq!(network.run_threaded(), Ok(NetworkStop::Idle));
+
+        // Until one of them is given something to pass round
+        network.programs[1].input.push_back(3);
+        assert_eq!(network.run_threaded(), Ok(NetworkStop::Halted));
+        assert_eq!(network.programs[1].input, vec![3]);
+    }
+
+    #[test]
+    fn test_run_threaded_packets() {
+        let mut network = amplifiers(&[9, 8, 7, 6, 5], Routing::Packets);
+        assert_eq!(network.run_threaded(), Err(ThreadedError::PacketRouting));
+        assert_eq!(network.programs.len(), 5);
+        assert_eq!(network.programs[0].input, vec![9, 0]);
+        assert_eq!(network.programs[0].steps, 0);
+    }
+}
@@ -0,0 +1,360 @@
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use std::io::Write;
+use std::ops::Range;
+
+use crate::disassembler::Operand;
+use crate::operation::Operation;
+use crate::program::{Instruction, Opcode};
+use crate::word::Word;
+
+// Set Program::trace to record every instruction the program executes. Instructions that pause
+// (IN with no input queued) or fault aren't recorded, since they never complete.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Trace<W = isize> {
+    pub filter: TraceFilter,
+    pub sink: TraceSink<W>,
+    pub steps: usize,
+}
+
+// Where the events go as the program runs
+pub enum TraceSink<W = isize> {
+    // Kept, to be looked at or written out with write_json_lines() afterwards
+    Events(Vec<TraceEvent<W>>),
+    // Written out as JSON Lines as each instruction completes. Writing stops at the first error,
+    // which is kept here.
+    JsonLines {
+        writer: Box<dyn Write + Send>,
+        error: Option<io::Error>,
+    },
+}
+
+// Which instructions to keep; None keeps everything. Steps are counted whether or not they're kept.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct TraceFilter {
+    pub ips: Option<Range<usize>>,
+    pub opcodes: Option<Vec<Opcode>>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TraceEvent<W = isize> {
+    pub step: usize,
+    pub ip: usize,
+    pub instruction: Instruction,
+    pub operation: &'static Operation,
+    pub operands: Vec<Operand<W>>,
+    // What each operand resolved to: the value read, or the address written for write operands
+    pub params: Vec<W>,
+    // (address, value) for every memory cell written
+    pub writes: Vec<(usize, W)>,
+    pub relative_base: usize,
+    pub new_relative_base: usize,
+}
+
+impl<W: Word> Trace<W> {
+    pub fn new(filter: TraceFilter) -> Trace<W> {
+        Trace {
+            filter,
+            ..Default::default()
+        }
+    }
+
+    // A trace that writes each event straight to writer, rather than keeping it
+    pub fn streaming<T: Write + Send + 'static>(filter: TraceFilter, writer: T) -> Trace<W> {
+        Trace {
+            filter,
+            sink: TraceSink::JsonLines {
+                writer: Box::new(writer),
+                error: None,
+            },
+            steps: 0,
+        }
+    }
+
+    // The events kept so far; always empty when they're being written out instead
+    pub fn events(&self) -> &[TraceEvent<W>] {
+        match &self.sink {
+            TraceSink::Events(events) => events,
+            TraceSink::JsonLines { .. } => &[],
+        }
+    }
+
+    // Count the step, then let the caller build the event only if the filter keeps it
+    pub(crate) fn record<F>(&mut self, ip: usize, opcode: Opcode, event: F)
+    where
+        F: FnOnce(usize) -> TraceEvent<W>,
+    {
+        let step = self.steps;
+        self.steps += 1;
+
+        if !self.filter.matches(ip, opcode) {
+            return;
+        }
+        match &mut self.sink {
+            TraceSink::Events(events) => events.push(event(step)),
+            TraceSink::JsonLines { error: Some(_), .. } => {}
+            TraceSink::JsonLines { writer, error } => {
+                if let Err(err) = writeln!(writer, "{}", event(step)) {
+                    *error = Some(err);
+                }
+            }
+        }
+    }
+
+    // Write out the events kept so far
+    pub fn write_json_lines<T: Write>(&self, mut output: T) -> io::Result<()> {
+        for event in self.events() {
+            writeln!(output, "{}", event)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl TraceFilter {
+    pub fn matches(&self, ip: usize, opcode: Opcode) -> bool {
+        let ip_matches = match &self.ips {
+            Some(ips) => ips.contains(&ip),
+            None => true,
+        };
+        let opcode_matches = match &self.opcodes {
+            Some(opcodes) => opcodes.contains(&opcode),
+            None => true,
+        };
+
+        ip_matches && opcode_matches
+    }
+}
+
+impl<W> Default for TraceSink<W> {
+    fn default() -> TraceSink<W> {
+        TraceSink::Events(vec![])
+    }
+}
+
+impl<W: Debug> Debug for TraceSink<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceSink::Events(events) => f.debug_tuple("Events").field(events).finish(),
+            TraceSink::JsonLines { error, .. } => {
+                f.debug_struct("JsonLines").field("error", error).finish()
+            }
+        }
+    }
+}
+
+// Writers can't be compared, so any two sinks writing JSON Lines are taken to be the same
+impl<W: PartialEq> PartialEq for TraceSink<W> {
+    fn eq(&self, other: &TraceSink<W>) -> bool {
+        match (self, other) {
+            (TraceSink::Events(a), TraceSink::Events(b)) => a == b,
+            (TraceSink::JsonLines { .. }, TraceSink::JsonLines { .. }) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<W: Eq> Eq for TraceSink<W> {}
+
+// One JSON object per event, with operands written the way the disassembler writes them, e.g.
+//   {"step":0,"ip":0,"instruction":1001,"opcode":1,"mnemonic":"ADD","operands":["4","#-3","4"],
+//    "params":[7,-3,4],"writes":[[4,4]],"relative_base":0}
+// new_relative_base is only included when the instruction changed it.
+impl<W: Word> Display for TraceEvent<W> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let operands: Vec<String> = self
+            .operands
+            .iter()
+            .map(|operand| json_string(&operand.to_string()))
+            .collect();
+        let params: Vec<String> = self.params.iter().map(|p| p.to_string()).collect();
+        let writes: Vec<String> = self
+            .writes
+            .iter()
+            .map(|(address, value)| format!("[{},{}]", address, value))
+            .collect();
+
+        write!(
+            f,
+            "{{\"step\":{},\"ip\":{},\"instruction\":{},\"opcode\":{},\"mnemonic\":{},\"operands\":[{}],\"params\":[{}],\"writes\":[{}],\"relative_base\":{}",
+            self.step,
+            self.ip,
+            self.instruction,
+            self.operation.opcode,
+            json_string(self.operation.mnemonic),
+            operands.join(","),
+            params.join(","),
+            writes.join(","),
+            self.relative_base,
+        )?;
+        if self.new_relative_base != self.relative_base {
+            write!(f, ",\"new_relative_base\":{}", self.new_relative_base)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+// s as a quoted JSON string; mnemonics can be anything a custom opcode was registered with
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::custom::CustomOpcodes;
+    use crate::program::{HaltReason, ParamAt, ParameterUse, Program};
+    use std::sync::{Arc, Mutex};
+
+    // A writer the test can still read from after handing it to the trace
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trace(source: &str, input: Vec<isize>, filter: TraceFilter) -> Trace {
+        let mut program = Program::new(assemble(source).unwrap(), Some(input));
+        program.trace = Some(Trace::new(filter));
+        assert_eq!(program.run(), Ok(HaltReason::Halted));
+        program.trace.unwrap()
+    }
+
+    const COUNTDOWN: &str = "
+                ARB #100
+                IN @0
+        loop:   OUT @0
+                ADD @0, #-1, @0
+                JT @0, #loop
+                HLT
+    ";
+
+    #[test]
+    fn test_trace() {
+        let trace = trace(COUNTDOWN, vec![2], TraceFilter::default());
+        let mut json_lines = vec![];
+        trace.write_json_lines(&mut json_lines).unwrap();
+
+        assert_eq!(trace.steps, 9);
+        assert_eq!(
+            String::from_utf8(json_lines)
+                .unwrap()
+                .lines()
+                .collect::<Vec<_>>(),
+            [
+                r##"{"step":0,"ip":0,"instruction":109,"opcode":9,"mnemonic":"ARB","operands":["#100"],"params":[100],"writes":[],"relative_base":0,"new_relative_base":100}"##,
+                r##"{"step":1,"ip":2,"instruction":203,"opcode":3,"mnemonic":"IN","operands":["@0"],"params":[100],"writes":[[100,2]],"relative_base":100}"##,
+                r##"{"step":2,"ip":4,"instruction":204,"opcode":4,"mnemonic":"OUT","operands":["@0"],"params":[2],"writes":[],"relative_base":100}"##,
+                r##"{"step":3,"ip":6,"instruction":21201,"opcode":1,"mnemonic":"ADD","operands":["@0","#-1","@0"],"params":[2,-1,100],"writes":[[100,1]],"relative_base":100}"##,
+                r##"{"step":4,"ip":10,"instruction":1205,"opcode":5,"mnemonic":"JT","operands":["@0","#4"],"params":[1,4],"writes":[],"relative_base":100}"##,
+                r##"{"step":5,"ip":4,"instruction":204,"opcode":4,"mnemonic":"OUT","operands":["@0"],"params":[1],"writes":[],"relative_base":100}"##,
+                r##"{"step":6,"ip":6,"instruction":21201,"opcode":1,"mnemonic":"ADD","operands":["@0","#-1","@0"],"params":[1,-1,100],"writes":[[100,0]],"relative_base":100}"##,
+                r##"{"step":7,"ip":10,"instruction":1205,"opcode":5,"mnemonic":"JT","operands":["@0","#4"],"params":[0,4],"writes":[],"relative_base":100}"##,
+                r##"{"step":8,"ip":13,"instruction":99,"opcode":99,"mnemonic":"HLT","operands":[],"params":[],"writes":[],"relative_base":100}"##,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_trace_filter() {
+        let steps_and_ips = |filter| -> Vec<(usize, usize)> {
+            trace(COUNTDOWN, vec![3], filter)
+                .events()
+                .iter()
+                .map(|event| (event.step, event.ip))
+                .collect()
+        };
+
+        assert_eq!(
+            steps_and_ips(TraceFilter {
+                opcodes: Some(vec![4]),
+                ..Default::default()
+            }),
+            vec![(2, 4), (5, 4), (8, 4)],
+        );
+        assert_eq!(
+            steps_and_ips(TraceFilter {
+                ips: Some(6..11),
+                ..Default::default()
+            }),
+            vec![(3, 6), (4, 10), (6, 6), (7, 10), (9, 6), (10, 10)],
+        );
+        assert_eq!(
+            steps_and_ips(TraceFilter {
+                ips: Some(0..8),
+                opcodes: Some(vec![1, 9]),
+            }),
+            vec![(0, 0), (3, 6), (6, 6), (9, 6)],
+        );
+    }
+
+    #[test]
+    fn test_trace_streaming() {
+        let kept = trace(COUNTDOWN, vec![2], TraceFilter::default());
+        let mut expected = vec![];
+        kept.write_json_lines(&mut expected).unwrap();
+
+        let buffer = SharedBuffer::default();
+        let mut program = Program::new(assemble(COUNTDOWN).unwrap(), Some(vec![2]));
+        program.trace = Some(Trace::streaming(TraceFilter::default(), buffer.clone()));
+        assert_eq!(program.run(), Ok(HaltReason::Halted));
+
+        assert_eq!(*buffer.0.lock().unwrap(), expected);
+        let trace = program.trace.unwrap();
+        assert!(trace.events().is_empty());
+        assert_eq!(trace.steps, 9);
+    }
+
+    #[test]
+    fn test_trace_self_modifying() {
+        // ADD #5, #0, 3 writes over its own last operand, which is traced as it was when it ran
+        let mut program = Program::new(vec![1101, 5, 0, 3, 99], None);
+        program.trace = Some(Trace::new(TraceFilter::default()));
+        assert_eq!(program.run(), Ok(HaltReason::Halted));
+
+        let trace = program.trace.unwrap();
+        assert_eq!(
+            trace.events()[0].to_string(),
+            r##"{"step":0,"ip":0,"instruction":1101,"opcode":1,"mnemonic":"ADD","operands":["#5","#0","3"],"params":[5,0,3],"writes":[[3,5]],"relative_base":0}"##,
+        );
+    }
+
+    #[test]
+    fn test_trace_escaping() {
+        const QUOTE: Operation = Operation {
+            opcode: 42,
+            mnemonic: "SAY\"\\",
+            params_at: &[ParamAt(0, ParameterUse::Read)],
+        };
+        let mut custom_opcodes = CustomOpcodes::new();
+        custom_opcodes.register(&QUOTE, |_, _| {});
+
+        let mut program = Program::new(vec![142, 7, 99], None);
+        program.custom_opcodes = Some(custom_opcodes);
+        program.trace = Some(Trace::new(TraceFilter::default()));
+        assert_eq!(program.run(), Ok(HaltReason::Halted));
+
+        let event = program.trace.unwrap().events()[0].to_string();
+        assert!(event.contains(r#""mnemonic":"SAY\"\\","#), "{}", event);
+        assert_eq!(json_string("a\nb"), r#""a\u000ab""#);
+    }
+}
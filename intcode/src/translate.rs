@@ -0,0 +1,416 @@
+use std::fmt::Write;
+
+use crate::control_flow::ControlFlowGraph;
+use crate::disassembler::{Line, Operand};
+use crate::operation::{Operation, HLT};
+use crate::program::{Memory, ParameterMode, ParameterUse};
+use ParameterMode::*;
+
+// Ahead-of-time translation of a program into the source of a Rust module, with a
+//   pub fn run<I: InputSource, O: OutputSink>(program: &mut Program<I, O>)
+//       -> Result<HaltReason, IntcodeError>
+// that does what program.run() does, only faster. Every basic block the control-flow graph finds is
+// compiled to native code, with its static jumps going straight to the block they name. Anything
+// else is handed over to the interpreter: jumps to addresses that don't start a block, anything
+// that faults or overflows (which the interpreter then reports), and every run of a program whose
+// code has been overwritten. A write into code hands over right away, so whatever was written is
+// what runs next. Whenever Program::needs_interpreter() is true, run() hands over from the start.
+// Custom opcodes don't decode when translating, so their instructions are left for the interpreter
+// as well. The module's runs_natively() says whether run() will start off in native code.
+//
+// The module can be written out by a build script and pulled in with include!().
+pub fn translate(memory: &Memory) -> String {
+    translate_patchable(memory, &[])
+}
+
+// Like translate(), for a program that's patched differently on each run, like day 2's noun and
+// verb. The patchable cells are read as the translated code runs instead of being compiled in, so
+// patching them doesn't count as overwriting the code. An instruction whose opcode is patchable is
+// left to the interpreter.
+pub fn translate_patchable(memory: &Memory, patchable: &[usize]) -> String {
+    let graph = ControlFlowGraph::new(memory);
+    let mut code_cells: Vec<usize> = graph
+        .blocks
+        .values()
+        .flat_map(|block| block.start..block.end)
+        .collect();
+    code_cells.sort_unstable();
+    code_cells.dedup();
+
+    let mut source = String::new();
+    let out = &mut source;
+    writeln!(
+        out,
+        "// Translated from a {}-word Intcode program by intcode::translate()",
+        memory.len()
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "use intcode::{{HaltReason, InputSource, IntcodeError, OutputSink, Program}};\n"
+    )
+    .unwrap();
+
+    writeln!(out, "// Every cell the translated code was compiled from").unwrap();
+    let code: Vec<String> = code_cells
+        .iter()
+        .filter(|address| !patchable.contains(address))
+        .map(|&address| format!("({}, {})", address, memory[address]))
+        .collect();
+    writeln!(
+        out,
+        "static CODE: [(usize, isize); {}] = [{}];\n",
+        code.len(),
+        code.join(", ")
+    )
+    .unwrap();
+
+    out.push_str(
+        "\
+pub fn runs_natively<I: InputSource, O: OutputSink>(program: &Program<I, O>) -> bool {
+    !program.needs_interpreter()
+        && CODE.iter().all(|&(address, value)| program.memory.read(address) == value)
+}
+
+#[allow(unreachable_code, unused_variables, clippy::all)]
+pub fn run<I: InputSource, O: OutputSink>(
+    program: &mut Program<I, O>,
+) -> Result<HaltReason, IntcodeError> {
+    if !runs_natively(program) {
+        return program.run();
+    }
+
+    let p = program;
+    loop {
+        match p.ip {
+",
+    );
+    for block in graph.blocks.values() {
+        writeln!(out, "            {} => {{", block.start).unwrap();
+        for (steps, line) in block.lines.iter().enumerate() {
+            translate_line(out, line, steps, &code_cells, patchable);
+        }
+        match block.lines.last() {
+            Some(Line::Instruction { operation, .. }) if operation.opcode != HLT.opcode => {
+                writeln!(out, "                p.ip = {};", block.end).unwrap();
+                writeln!(out, "                p.steps += {};", block.lines.len()).unwrap();
+            }
+            _ => {}
+        }
+        writeln!(out, "            }}").unwrap();
+    }
+    out.push_str(
+        "            _ => return p.run(),
+        }
+    }
+}
+",
+    );
+
+    let ranges: Vec<String> = ranges(&code_cells)
+        .iter()
+        .map(|(start, end)| format!("{}..={}", start, end - 1))
+        .collect();
+    writeln!(
+        out,
+        "
+#[allow(dead_code, unused_variables)]
+fn is_code(address: usize) -> bool {{
+    {}
+}}",
+        if ranges.is_empty() {
+            "false".to_string()
+        } else {
+            format!("matches!(address, {})", ranges.join(" | "))
+        }
+    )
+    .unwrap();
+
+    out.push_str(
+        "
+#[allow(dead_code)]
+fn relative(relative_base: usize, offset: isize) -> Option<usize> {
+    match (relative_base as isize).checked_add(offset) {
+        Some(address) if address >= 0 => Some(address as usize),
+        _ => None,
+    }
+}
+
+// Carry on in the interpreter from ip, once `steps` more instructions have completed
+fn interpret<I: InputSource, O: OutputSink>(
+    p: &mut Program<I, O>,
+    ip: usize,
+    steps: usize,
+) -> Result<HaltReason, IntcodeError> {
+    p.ip = ip;
+    p.steps += steps;
+    p.run()
+}
+
+fn pause<I: InputSource, O: OutputSink>(
+    p: &mut Program<I, O>,
+    ip: usize,
+    steps: usize,
+    halt_reason: HaltReason,
+) -> Result<HaltReason, IntcodeError> {
+    p.ip = ip;
+    p.steps += steps;
+    p.halt_reason = Some(halt_reason.clone());
+    p.fault = None;
+    Ok(halt_reason)
+}
+",
+    );
+
+    source
+}
+
+// Native code for one line of a block, where `steps` instructions of the block have already run.
+// Faults hand over to the interpreter before anything has changed, so it can report them itself.
+fn translate_line(
+    out: &mut String,
+    line: &Line,
+    steps: usize,
+    code_cells: &[usize],
+    patchable: &[usize],
+) {
+    let indent = "                ";
+    let handover = format!("return interpret(p, {}, {});", line.address(), steps);
+    let (address, operation, operands) = match line {
+        Line::Instruction {
+            address,
+            operation,
+            operands,
+            ..
+        } if !patchable.contains(address) => (*address, *operation, operands),
+        _ => {
+            writeln!(out, "{}// {}: {}", indent, line.address(), line).unwrap();
+            writeln!(out, "{}{}", indent, handover).unwrap();
+            return;
+        }
+    };
+    let next = address + operation.size();
+    let fault = format!("return interpret(p, {}, {})", address, steps);
+
+    writeln!(out, "{}// {}: {}", indent, address, line).unwrap();
+    writeln!(out, "{}{{", indent).unwrap();
+    let indent = "                    ";
+
+    // x0, x1... for values read, w0, w1... for addresses written. A patchable operand is read from
+    // its cell, and anything it addresses is checked as it runs, like a relative one.
+    let patched = |idx: usize| patchable.contains(&(address + 1 + idx));
+    let mut static_code_write = false;
+    for (idx, operand) in operands.iter().enumerate() {
+        let Operand {
+            mode,
+            param_use,
+            value,
+        } = *operand;
+        let statement = match (mode, param_use) {
+            (PositionMode, ParameterUse::Read) if patched(idx) => format!(
+                "let x{} = match p.memory.read({}) {{ address if address >= 0 => p.memory.read(address as usize), _ => {} }};",
+                idx,
+                address + 1 + idx,
+                fault
+            ),
+            (PositionMode, ParameterUse::Write) if patched(idx) => format!(
+                "let w{} = match p.memory.read({}) {{ address if address >= 0 => address as usize, _ => {} }};",
+                idx,
+                address + 1 + idx,
+                fault
+            ),
+            (PositionMode, ParameterUse::Read) if value < 0 => {
+                format!("let x{}: isize = {};", idx, fault)
+            }
+            (PositionMode, ParameterUse::Write) if value < 0 => {
+                format!("let w{}: usize = {};", idx, fault)
+            }
+            (PositionMode, ParameterUse::Read) => {
+                format!("let x{} = p.memory.read({});", idx, value)
+            }
+            (PositionMode, ParameterUse::Write) => {
+                static_code_write = code_cells.binary_search(&(value as usize)).is_ok();
+                format!("let w{} = {};", idx, value)
+            }
+            // The disassembler never produces immediate writes
+            (ImmediateMode, _) if patched(idx) => {
+                format!("let x{} = p.memory.read({});", idx, address + 1 + idx)
+            }
+            (ImmediateMode, _) => format!("let x{}: isize = {};", idx, value),
+            (RelativeMode, ParameterUse::Read) => format!(
+                "let x{} = match relative(p.relative_base, {}) {{ Some(address) => p.memory.read(address), None => {} }};",
+                idx,
+                operand_value(address, idx, value, patchable),
+                fault
+            ),
+            (RelativeMode, ParameterUse::Write) => format!(
+                "let w{} = match relative(p.relative_base, {}) {{ Some(address) => address, None => {} }};",
+                idx,
+                operand_value(address, idx, value, patchable),
+                fault
+            ),
+        };
+        writeln!(out, "{}{}", indent, statement).unwrap();
+    }
+
+    let jump = |condition: &str| {
+        let target = match line.jump_target() {
+            Some(target) if !patched(1) => target.to_string(),
+            _ => format!("{{ if x1 < 0 {{ {}; }} x1 as usize }}", fault),
+        };
+        format!(
+            "if {} {{ p.ip = {}; p.steps += {}; continue; }}",
+            condition,
+            target,
+            steps + 1
+        )
+    };
+    let statement = match operation.opcode {
+        // ADD: [c] = [a] + [b]
+        1 => format!(
+            "match isize::checked_add(x0, x1) {{ Some(value) => p.memory.write(w2, value), None => {} }}",
+            fault
+        ),
+        // MULTIPLY: [c] = [a] * [b]
+        2 => format!(
+            "match isize::checked_mul(x0, x1) {{ Some(value) => p.memory.write(w2, value), None => {} }}",
+            fault
+        ),
+        // INPUT: [a] = input
+        3 => format!(
+            "match p.input.read() {{ Some(value) => p.memory.write(w0, value), None => return pause(p, {}, {}, HaltReason::NeedsInput) }}",
+            address, steps
+        ),
+        // OUTPUT: output [a]
+        4 => "p.output.write(x0);".to_string(),
+        // JUMP-IF-TRUE, JUMP-IF-FALSE: if [a] is (not) 0, ip = [b]
+        5 => jump("x0 != 0"),
+        6 => jump("x0 == 0"),
+        // LESS THAN, EQUALS: [c] = [a] < [b], [a] == [b]
+        7 => "p.memory.write(w2, (x0 < x1) as isize);".to_string(),
+        8 => "p.memory.write(w2, (x0 == x1) as isize);".to_string(),
+        // ADJUST RELATIVE BASE: relative_base += [a]
+        9 => format!(
+            "p.relative_base = match (p.relative_base as isize).checked_add(x0) {{ Some(relative_base) => relative_base as usize, None => {} }};",
+            fault
+        ),
+        // HALT
+        99 => format!(
+            "return pause(p, {}, {}, HaltReason::Halted);",
+            address,
+            steps + 1
+        ),
+        _ => unreachable!("No translation for {:?}", operation),
+    };
+    writeln!(out, "{}{}", indent, statement).unwrap();
+
+    // Whatever was written over the code has to be what runs next
+    if let Some(write_idx) = write_param(operation) {
+        let handover = format!("return interpret(p, {}, {});", next, steps + 1);
+        if operands[write_idx].mode == RelativeMode || patched(write_idx) {
+            writeln!(
+                out,
+                "{}if is_code(w{}) {{ {} }}",
+                indent, write_idx, handover
+            )
+            .unwrap();
+        } else if static_code_write {
+            writeln!(out, "{}{}", indent, handover).unwrap();
+        }
+    }
+
+    writeln!(out, "                }}").unwrap();
+}
+
+// An operand's value: compiled in, or read from its cell if it's patchable
+fn operand_value(address: usize, idx: usize, value: isize, patchable: &[usize]) -> String {
+    let cell = address + 1 + idx;
+    if patchable.contains(&cell) {
+        format!("p.memory.read({})", cell)
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_param(operation: &Operation) -> Option<usize> {
+    operation
+        .params_at
+        .iter()
+        .find(|pat| pat.1 == ParameterUse::Write)
+        .map(|pat| pat.0)
+}
+
+// Runs of consecutive addresses, as (start, end) with end exclusive
+fn ranges(addresses: &[usize]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = vec![];
+    for &address in addresses {
+        match ranges.last_mut() {
+            Some((_, end)) if *end == address => *end += 1,
+            _ => ranges.push((address, address + 1)),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_translate() {
+        let memory = assemble(
+            "
+                    ARB #100                ; 0
+            loop:   IN @0                   ; 2
+                    JF @0, #done            ; 4
+                    ADD @0, total, total    ; 7
+                    JT #1, #loop            ; 11
+            done:   ADD #0, #99, patch      ; 14
+            patch:  OUT total               ; 18
+                    HLT                     ; 20
+            total:  .data 0                 ; 21
+            ",
+        )
+        .unwrap();
+        let source = translate(&memory);
+        let lines: Vec<&str> = source.lines().map(str::trim).collect();
+
+        assert!(lines.contains(&"static CODE: [(usize, isize); 21] = [(0, 109), (1, 100), (2, 203), (3, 0), (4, 1206), (5, 0), (6, 14), (7, 201), (8, 0), (9, 21), (10, 21), (11, 1105), (12, 1), (13, 2), (14, 1101), (15, 0), (16, 99), (17, 18), (18, 4), (19, 21), (20, 99)];"));
+        assert!(lines.contains(&"matches!(address, 0..=20)"));
+
+        // One arm per block, jumping straight to the blocks it names
+        let arms: Vec<&str> = lines
+            .iter()
+            .copied()
+            .filter(|line| line.ends_with("=> {"))
+            .collect();
+        assert_eq!(arms, vec!["0 => {", "2 => {", "7 => {", "14 => {"]);
+        assert!(lines.contains(&"if x0 == 0 { p.ip = 14; p.steps += 2; continue; }"));
+        assert!(lines.contains(&"if x0 != 0 { p.ip = 2; p.steps += 2; continue; }"));
+        assert!(lines.contains(&"return pause(p, 20, 3, HaltReason::Halted);"));
+
+        // Writes into code hand over to the interpreter, and writes through @ operands are checked
+        assert!(lines.contains(&"if is_code(w0) { return interpret(p, 4, 1); }"));
+        assert!(!lines.contains(&"return interpret(p, 11, 1);"));
+        assert!(lines.contains(&"return interpret(p, 18, 1);"));
+
+        // Patchable operands are read as the code runs, and aren't checked before it starts
+        let source = translate_patchable(&memory, &[1, 6, 17, 20]);
+        let lines: Vec<&str> = source.lines().map(str::trim).collect();
+        assert!(lines.contains(&"static CODE: [(usize, isize); 17] = [(0, 109), (2, 203), (3, 0), (4, 1206), (5, 0), (7, 201), (8, 0), (9, 21), (10, 21), (11, 1105), (12, 1), (13, 2), (14, 1101), (15, 0), (16, 99), (18, 4), (19, 21)];"));
+        assert!(lines.contains(&"let x0 = p.memory.read(1);"));
+        assert!(lines.contains(&"if x0 == 0 { p.ip = { if x1 < 0 { return interpret(p, 4, 1); } x1 as usize }; p.steps += 2; continue; }"));
+        assert!(lines.contains(&"let w2 = match p.memory.read(17) { address if address >= 0 => address as usize, _ => return interpret(p, 14, 0) };"));
+        assert!(lines.contains(&"if is_code(w2) { return interpret(p, 18, 1); }"));
+
+        // An instruction whose opcode is patchable is left to the interpreter
+        assert!(lines.contains(&"return interpret(p, 20, 2);"));
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(ranges(&[]), vec![]);
+        assert_eq!(ranges(&[0, 1, 2, 5, 7, 8]), vec![(0, 3), (5, 6), (7, 9)]);
+    }
+}
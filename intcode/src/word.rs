@@ -0,0 +1,146 @@
+use std::convert::TryFrom;
+use std::fmt::{Debug, Display};
+
+#[cfg(feature = "bigint")]
+use num_bigint::BigInt;
+#[cfg(feature = "bigint")]
+use num_traits::ToPrimitive;
+
+// What a memory cell holds. Program runs on isize words by default, and on any other Word type
+// through Program::from_words or Program::with_devices:
+//   isize, i64  trap with IntcodeError::Overflow when ADD or MUL overflows
+//   i128        the same, with a lot more room
+//   BigInt      never overflows (needs the "bigint" feature)
+// Default must be 0.
+pub trait Word: Clone + Default + Eq + Ord + Debug + Display {
+    fn from_isize(value: isize) -> Self;
+
+    // None if the value doesn't fit, which makes it an invalid instruction or address
+    fn to_isize(&self) -> Option<isize>;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+}
+
+macro_rules! impl_word {
+    ($($t:ty),*) => {
+        $(
+            impl Word for $t {
+                #[inline]
+                fn from_isize(value: isize) -> $t {
+                    <$t>::try_from(value).unwrap()
+                }
+
+                #[inline]
+                fn to_isize(&self) -> Option<isize> {
+                    isize::try_from(*self).ok()
+                }
+
+                #[inline]
+                fn checked_add(&self, other: &$t) -> Option<$t> {
+                    <$t>::checked_add(*self, *other)
+                }
+
+                #[inline]
+                fn checked_mul(&self, other: &$t) -> Option<$t> {
+                    <$t>::checked_mul(*self, *other)
+                }
+            }
+        )*
+    };
+}
+
+impl_word!(isize, i64, i128);
+
+#[cfg(feature = "bigint")]
+impl Word for BigInt {
+    fn from_isize(value: isize) -> BigInt {
+        BigInt::from(value)
+    }
+
+    fn to_isize(&self) -> Option<isize> {
+        ToPrimitive::to_isize(self)
+    }
+
+    fn checked_add(&self, other: &BigInt) -> Option<BigInt> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &BigInt) -> Option<BigInt> {
+        Some(self * other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::IntcodeError;
+    use crate::program::{HaltReason::*, Program};
+
+    // Square a number n times, outputting each square
+    fn squares<W: Word>(start: isize, n: isize) -> (Vec<W>, Result<(), IntcodeError>) {
+        let memory: Vec<W> = vec![
+            1101, 0, start, 100, // ADD #0, #start, 100
+            1101, 0, n, 101, //     ADD #0, #n, 101
+            2, 100, 100, 100, //    loop: MUL 100, 100, 100
+            4, 100, //              OUT 100
+            101, -1, 101, 101, //   ADD 101, #-1, 101
+            1005, 101, 8,  //        JT 101, #loop
+            99, //                  HLT
+        ]
+        .into_iter()
+        .map(W::from_isize)
+        .collect();
+
+        let mut program = Program::from_words(memory, None);
+        let result = program
+            .run()
+            .map(|halt_reason| assert_eq!(halt_reason, Halted));
+        (program.output, result)
+    }
+
+    #[test]
+    fn test_word_modes() {
+        let overflow_at = |ip, instruction| IntcodeError::Overflow { ip, instruction };
+        let overflow = Err(overflow_at(8, 2));
+
+        // 2^32 * 2^32 is too big for 64 bits, but not 128
+        assert_eq!(
+            squares::<isize>(1 << 16, 2),
+            (vec![1 << 32], overflow.clone())
+        );
+        assert_eq!(
+            squares::<i64>(1 << 16, 2),
+            (vec![1 << 32], overflow.clone())
+        );
+        assert_eq!(
+            squares::<i128>(1 << 16, 3),
+            (vec![1 << 32, 1 << 64], overflow)
+        );
+        assert_eq!(squares::<i128>(-3, 3), (vec![9, 81, 6561], Ok(())));
+
+        // Instructions and addresses still have to fit in an isize
+        let mut program = Program::from_words(vec![1i128 << 64, 99], None);
+        assert_eq!(
+            program.run(),
+            Err(IntcodeError::UnknownOpcode {
+                ip: 0,
+                instruction: -1,
+            }),
+        );
+        let mut program = Program::from_words(vec![4i128, 1 << 64, 99], None);
+        assert_eq!(program.run(), Err(overflow_at(0, 4)));
+    }
+
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn test_word_modes_bigint() {
+        // 2^128, which overflowed i128
+        let (output, result) = squares::<BigInt>(1 << 16, 3);
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            output.last().unwrap().to_string(),
+            "340282366920938463463374607431768211456",
+        );
+    }
+}
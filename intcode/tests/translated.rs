@@ -0,0 +1,143 @@
+// Every program the tests/translated crate translates ahead of time, run in native code next to the
+// interpreter
+use intcode::{parse_memory, HaltReason, Profile, Program};
+use translated::PROGRAMS;
+
+// The input values each program is fed, one at a time as it asks for them, for each run
+fn runs(name: &str) -> Vec<Vec<isize>> {
+    match name {
+        "day_02" | "day_02_noun_verb" => vec![vec![]],
+        "day_05" => vec![vec![1], vec![5]],
+        // Each phase setting, then signals for as long as the amplifier wants them
+        "day_07" => (0..=9)
+            .map(|phase| [phase, 7].iter().copied().chain(vec![9; 20]).collect())
+            .collect(),
+        "day_09" => vec![vec![1], vec![2]],
+        // The colour of each panel the robot is over
+        "day_11" => vec![(0..500).map(|i| i % 3 % 2).collect()],
+        "day_13" | "day_17" => vec![vec![]],
+        // Joystick moves, until the ball gets past the paddle
+        "day_13_quarters" => vec![(0..3000).map(|i| i % 3 - 1).collect()],
+        // Droid movement commands
+        "day_15" => vec![(0..500).map(|i| i % 4 + 1).collect()],
+        // The movement routines, and no video feed
+        "day_17_awake" => vec![concat!(
+            "A,B,A,B,C,C,B,A,B,C\n",
+            "L,4,R,8,L,6,L,10\n",
+            "L,6,R,8,R,10,L,6,L,6\n",
+            "L,4,L,4,L,10\n",
+            "n\n"
+        )
+        .bytes()
+        .map(isize::from)
+        .collect()],
+        _ => panic!("No runs given for {}", name),
+    }
+}
+
+// What each run patches into the program's patchable cells
+fn patches(name: &str) -> Vec<Vec<isize>> {
+    match name {
+        // Part 1's 1202, part 2's answer, and the corners
+        "day_02_noun_verb" => vec![vec![12, 2], vec![65, 77], vec![0, 0], vec![99, 99]],
+        _ => vec![vec![]],
+    }
+}
+
+fn memory(name: &str) -> Vec<isize> {
+    let translated = PROGRAMS.iter().find(|t| t.name == name).unwrap();
+    let mut memory = parse_memory(translated.input).unwrap();
+    for &(address, value) in translated.patch {
+        memory[address] = value;
+    }
+    memory
+}
+
+#[test]
+fn test_translated() {
+    for translated in PROGRAMS.iter() {
+        let (name, run) = (translated.name, translated.run);
+
+        for values in patches(name) {
+            let mut memory = memory(name);
+            for (&address, &value) in translated.patchable.iter().zip(&values) {
+                memory[address] = value;
+            }
+
+            for inputs in runs(name) {
+                let mut interpreted = Program::new(memory.clone(), None);
+                let mut native = Program::new(memory.clone(), None);
+                assert!((translated.runs_natively)(&native), "{}", name);
+
+                let mut inputs = inputs.into_iter();
+                loop {
+                    let halt_reason = interpreted.run();
+                    assert_eq!(run(&mut native), halt_reason, "{}", name);
+                    // Output, memory, ip, steps and all
+                    assert_eq!(native, interpreted, "{}", name);
+
+                    match (halt_reason, inputs.next()) {
+                        (Ok(HaltReason::NeedsInput), Some(value)) => {
+                            interpreted.input.push_back(value);
+                            native.input.push_back(value);
+                        }
+                        _ => break,
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_translated_falls_back() {
+    let runs_natively = |name: &str, program: &Program| {
+        let translated = PROGRAMS.iter().find(|t| t.name == name).unwrap();
+        (translated.runs_natively)(program)
+    };
+
+    // Patching a cell that wasn't translated as patchable changes the code
+    let mut program = Program::new(memory("day_02"), None);
+    assert!(runs_natively("day_02", &program));
+    program.memory[1] = 12;
+    assert!(!runs_natively("day_02", &program));
+    assert!(runs_natively("day_02_noun_verb", &program));
+    program.memory[3] = 1;
+    assert!(!runs_natively("day_02_noun_verb", &program));
+
+    // Nor does a patched program match the translation of the unpatched one
+    let program = Program::new(memory("day_13_quarters"), None);
+    assert!(runs_natively("day_13_quarters", &program));
+    assert!(!runs_natively("day_13", &program));
+
+    // Anything only the interpreter keeps up hands the run over to it
+    let mut program = Program::new(memory("day_17"), None);
+    program.profile = Some(Profile::new());
+    assert!(!runs_natively("day_17", &program));
+}
+
+#[test]
+fn test_translated_answers() {
+    let translated = |name: &str| PROGRAMS.iter().find(|t| t.name == name).unwrap();
+
+    // Day 2, both parts
+    let day_02 = translated("day_02_noun_verb");
+    for &(noun, verb, answer) in &[(12, 2, 3790645), (65, 77, 19690720)] {
+        let mut program = Program::new(memory("day_02_noun_verb"), None);
+        program.memory[1] = noun;
+        program.memory[2] = verb;
+        assert!((day_02.runs_natively)(&program));
+        assert_eq!((day_02.run)(&mut program), Ok(HaltReason::Halted));
+        assert_eq!(program.memory[0], answer);
+    }
+
+    // Day 17 part 2, where the dust collected is the last value output
+    let day_17 = translated("day_17_awake");
+    let mut program = Program::new(memory("day_17_awake"), None);
+    program
+        .input
+        .extend(runs("day_17_awake")[0].iter().copied());
+    assert!((day_17.runs_natively)(&program));
+    assert_eq!((day_17.run)(&mut program), Ok(HaltReason::Halted));
+    assert_eq!(program.output.last(), Some(&684691));
+}
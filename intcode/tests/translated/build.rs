@@ -0,0 +1,93 @@
+// Translate the input of every Intcode day into Rust ahead of time, along with the patched programs
+// the days actually run; lib.rs includes them all, and intcode's tests/translated.rs checks each one
+// against the interpreter
+use intcode::{parse_memory, translate_patchable};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    let root = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("../../..");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+
+    let mut days: Vec<String> = fs::read_dir(&root)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("day_"))
+        .collect();
+    days.sort();
+
+    let mut modules = String::new();
+    let mut programs = vec![];
+    for day in days {
+        // The days that run Intcode are the ones that use the library
+        let manifest = fs::read_to_string(root.join(&day).join("Cargo.toml")).unwrap_or_default();
+        if !manifest.contains("intcode") {
+            continue;
+        }
+
+        let input_path = root
+            .join(&day)
+            .join("src/input.txt")
+            .canonicalize()
+            .unwrap();
+        println!("cargo:rerun-if-changed={}", input_path.display());
+        let memory = parse_memory(&fs::read_to_string(&input_path).unwrap()).unwrap();
+
+        for (name, patch, patchable) in variants(&day) {
+            let mut memory = memory.clone();
+            for &(address, value) in &patch {
+                memory[address] = value;
+            }
+            fs::write(
+                out_dir.join(format!("{}.rs", name)),
+                translate_patchable(&memory, &patchable),
+            )
+            .unwrap();
+
+            modules += &format!(
+                "mod {} {{\n    include!(concat!(env!(\"OUT_DIR\"), \"/{}.rs\"));\n}}\n",
+                name, name
+            );
+            programs.push(format!(
+                "    Translated {{ name: {:?}, input: include_str!({:?}), patch: &{:?}, patchable: &{:?}, run: {}::run, runs_natively: {}::runs_natively }},\n",
+                name,
+                input_path.display().to_string(),
+                patch,
+                patchable,
+                name,
+                name
+            ));
+        }
+    }
+
+    fs::write(
+        out_dir.join("programs.rs"),
+        format!(
+            "{}\npub const PROGRAMS: [Translated; {}] = [\n{}];\n",
+            modules,
+            programs.len(),
+            programs.concat()
+        ),
+    )
+    .unwrap();
+}
+
+// (address, value) for each cell patched
+type Patch = Vec<(usize, isize)>;
+
+// Each program a day runs, as (name, patch, patchable cells): the input as it is, and then the
+// input with whatever the day patches into it first
+fn variants(day: &str) -> Vec<(String, Patch, Vec<usize>)> {
+    let mut variants = vec![(day.to_string(), vec![], vec![])];
+    match day {
+        // The noun and verb, which change on every run
+        "day_02" => variants.push(("day_02_noun_verb".to_string(), vec![], vec![1, 2])),
+        // Two quarters, to play for free
+        "day_13" => variants.push(("day_13_quarters".to_string(), vec![(0, 2)], vec![])),
+        // Waking the robot up
+        "day_17" => variants.push(("day_17_awake".to_string(), vec![(0, 2)], vec![])),
+        _ => {}
+    }
+    variants
+}
@@ -0,0 +1,18 @@
+use intcode::{HaltReason, IntcodeError, Program};
+
+pub type Run = fn(&mut Program) -> Result<HaltReason, IntcodeError>;
+
+pub struct Translated {
+    pub name: &'static str,
+    pub input: &'static str,
+    // Patched into the input before it was translated, and to be patched in before running it
+    pub patch: &'static [(usize, isize)],
+    // Cells each run can patch however it likes, and still run natively
+    pub patchable: &'static [usize],
+    pub run: Run,
+    pub runs_natively: fn(&Program) -> bool,
+}
+
+// Every Intcode day's input translated into Rust by build.rs, along with the patched programs the
+// days run
+include!(concat!(env!("OUT_DIR"), "/programs.rs"));